@@ -20,10 +20,20 @@ const dbg = debug('c2pa:Validator');
  * @public
  */
 export class Validator {
+  // The detector also finds stores in JPEG XL (`image/jxl`) and FLAC
+  // (`audio/flac`), which the SDK used by the toolkit has no readers for, so
+  // they are not accepted here
   static readonly VALID_MIME_TYPES = [
     'application/c2pa',
+    'application/epub+zip',
     'application/mp4',
     'application/pdf',
+    'application/vnd.oasis.opendocument.presentation',
+    'application/vnd.oasis.opendocument.spreadsheet',
+    'application/vnd.oasis.opendocument.text',
+    'application/vnd.openxmlformats-officedocument.presentationml.presentation',
+    'application/vnd.openxmlformats-officedocument.spreadsheetml.sheet',
+    'application/vnd.openxmlformats-officedocument.wordprocessingml.document',
     'application/x-c2pa-manifest-store',
     'audio/mp4',
     'audio/mpeg',
@@ -31,6 +41,7 @@ export class Validator {
     'audio/wav',
     'audio/x-wav',
     'image/avif',
+    'image/gif',
     'image/heic',
     'image/heif',
     'image/jpeg',
@@ -82,13 +93,14 @@ export class Validator {
    * Scans an individual binary chunk for a C2PA metadata marker
    *
    * @param chunk - the chunk to check for the metadata marker
+   * @param isHead - whether the chunk only holds the first bytes of the asset
    */
-  async scanChunk(chunk: ArrayBuffer) {
+  async scanChunk(chunk: ArrayBuffer, isHead = false) {
     const wasm = await detectorWasm();
 
     dbg('Scanning buffer for C2PA marker with length %d', chunk.byteLength);
     // TODO: Add support for transferable objects
-    const result = await this.#pool.scanInput(wasm, chunk);
+    const result = await this.#pool.scanInput(wasm, chunk, isHead);
     dbg('Scanned buffer and got result', result);
     return result;
   }
@@ -105,6 +117,7 @@ export class Validator {
    */
   async scanInput(input: ArrayBuffer | Blob) {
     let buffer: ArrayBuffer | null = null;
+    let isHead = false;
 
     if (input instanceof ArrayBuffer) {
       buffer = input;
@@ -112,8 +125,12 @@ export class Validator {
       // Only send this as a transferable object if we are extracting an array
       // buffer from a blob, since we won't be re-using this buffer anywhere else
       const fullBuffer = await input.arrayBuffer();
-      if (this.#detectionLength > 0) {
+      if (
+        this.#detectionLength > 0 &&
+        fullBuffer.byteLength > this.#detectionLength
+      ) {
         buffer = fullBuffer.slice(0, this.#detectionLength);
+        isHead = true;
       } else {
         buffer = fullBuffer;
       }
@@ -123,6 +140,6 @@ export class Validator {
       throw new InvalidInputError();
     }
 
-    return this.scanChunk(buffer);
+    return this.scanChunk(buffer, isHead);
   }
 }
@@ -10,8 +10,10 @@
 import { setupWorker } from './src/lib/pool/worker';
 
 import {
+  DetectionResult,
   default as initDetector,
   scan_array_buffer,
+  scan_head_array_buffer,
 } from '@contentauth/detector';
 import {
   AssetReport,
@@ -25,6 +27,7 @@ import {
 export interface IScanResult {
   found: boolean;
   offset?: number;
+  result?: DetectionResult;
 }
 
 const worker = {
@@ -58,11 +61,21 @@ const worker = {
   async scanInput(
     wasm: WebAssembly.Module,
     buffer: ArrayBuffer,
+    isHead = false,
   ): Promise<IScanResult> {
     await initDetector(wasm);
     try {
-      const offset = await scan_array_buffer(buffer);
-      return { found: true, offset };
+      const result = isHead
+        ? scan_head_array_buffer(buffer)
+        : scan_array_buffer(buffer);
+      switch (result.kind) {
+        case 'manifestStore':
+        case 'damaged':
+        case 'remoteReference':
+          return { found: true, offset: result.offset, result };
+        default:
+          return { found: false, result };
+      }
     } catch (err) {
       return { found: false };
     }
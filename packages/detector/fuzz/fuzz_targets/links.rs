@@ -0,0 +1,19 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Drives the HTML and `Link` header parsers, and URL resolution, with arbitrary text.
+
+#![no_main]
+
+use detector::{html_manifest_links, link_header_manifest_links};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    html_manifest_links(&text, "https://example.com/pages/index.html");
+    link_header_manifest_links(&text, "https://example.com/images/photo.jpg");
+});
@@ -0,0 +1,96 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Checks shared by the fuzz targets.
+//!
+//! Besides not panicking, which in safe Rust also covers reading out of bounds,
+//! every scan must give the same answer whether the input is fed whole, in chunks
+//! or through a seekable reader, and must only report locations within the input.
+
+use detector::{
+    detect, detect_head, detect_reader, extract_store, parse_tree, plan_ranges, scan_all,
+    scan_all_reader, sniff_format, summarize, summarize_store, DetectionResult, Occurrence,
+    StreamDetector,
+};
+use std::io::Cursor;
+
+/// Chunk lengths the input is fed in, picked from the input itself so that the
+/// fuzzer explores them along with the data
+const CHUNK_LENS: [usize; 6] = [1, 2, 7, 64, 1000, 4096];
+
+/// Runs every entry point over `data`
+pub fn check(data: &[u8]) {
+    let result = detect(data);
+    let report = scan_all(data);
+    let chunk_len = CHUNK_LENS[data.len() % CHUNK_LENS.len()];
+
+    let mut detector = StreamDetector::new();
+    feed(&mut detector, data, chunk_len);
+    assert_eq!(detector.finish(), result, "chunks of {}", chunk_len);
+    let mut detector = StreamDetector::exhaustive();
+    feed(&mut detector, data, chunk_len);
+    assert_eq!(detector.finish_report(), report, "chunks of {}", chunk_len);
+
+    assert_eq!(detect_reader(&mut Cursor::new(data)).unwrap(), result);
+    assert_eq!(scan_all_reader(&mut Cursor::new(data)).unwrap(), report);
+
+    if let DetectionResult::ManifestStore { offset, length, .. } = result {
+        assert!(offset + length.unwrap_or(1) <= data.len());
+    }
+    for occurrence in &report.occurrences {
+        assert!(occurrence.offset() < data.len());
+        if let Occurrence::ManifestStore {
+            offset,
+            length: Some(length),
+        } = occurrence
+        {
+            assert!(offset + length <= data.len());
+        }
+    }
+
+    if let Some(store) = extract_store(data) {
+        assert!(store.bytes.len() <= data.len());
+    }
+    parse_tree(data);
+    summarize(data);
+    sniff_format(data);
+    detect_head(&data[..data.len() / 2]);
+    plan_ranges(&data[..data.len() / 2], data.len());
+}
+
+/// Runs `check` on `data` as an asset starting with `header`, which is added when
+/// missing so that arbitrary input reaches the walker for that format
+pub fn check_with_header(header: &[u8], data: &[u8]) {
+    if data.starts_with(header) {
+        check(data);
+    } else {
+        let mut asset = header.to_vec();
+        asset.extend_from_slice(data);
+        check(&asset);
+    }
+}
+
+/// Runs the store parsers on `data` as a bare JUMBF manifest store, and `check`
+/// on it as an asset of unknown format
+pub fn check_store(data: &[u8]) {
+    parse_tree(data);
+    summarize_store(data);
+    check(data);
+}
+
+/// Pushes `data` in chunks of `chunk_len`, jumping over the ranges the detector skips
+fn feed(detector: &mut StreamDetector, data: &[u8], chunk_len: usize) {
+    let mut offset = 0;
+    while offset < data.len() {
+        offset += detector.take_skip(data.len() - offset);
+        let end = data.len().min(offset + chunk_len);
+        if offset >= end || detector.push(&data[offset..end]) {
+            break;
+        }
+        offset = end;
+    }
+}
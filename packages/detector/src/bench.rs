@@ -0,0 +1,81 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Timings of the input paths of the exports on a large asset. As building the
+//! asset takes hundreds of megabytes, they are ignored unless run with
+//! `wasm-pack test --node --release -- --include-ignored bench`.
+//!
+//! The old path deserializes the whole input into a `serde_bytes::ByteBuf` before
+//! scanning it, the new ones copy it in bulk or read it in chunks.
+
+use crate::bmff::C2PA_UUID;
+use crate::detection::{self, DetectionResult};
+use crate::extract::extract_store;
+use crate::input;
+use js_sys::{Date, SharedArrayBuffer, Uint8Array};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::*;
+
+/// Size of the media data preceding the store
+const MDAT_LEN: usize = 100 * 1024 * 1024;
+const RUNS: u32 = 5;
+
+/// An MP4 whose C2PA `uuid` box follows 100 MB of media data
+fn video() -> Vec<u8> {
+    let jpeg = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+    let store = extract_store(jpeg).unwrap().bytes;
+
+    let mut asset = b"\0\0\0\x10ftypisom\0\0\0\0\0\0\0\x01mdat".to_vec();
+    asset.extend_from_slice(&(16 + MDAT_LEN as u64).to_be_bytes());
+    asset.resize(asset.len() + MDAT_LEN, 0);
+
+    let mut content = C2PA_UUID.to_vec();
+    content.extend_from_slice(&[0; 4]);
+    content.extend_from_slice(b"manifest\0");
+    content.extend_from_slice(&[0; 8]);
+    content.extend_from_slice(&store);
+    asset.extend_from_slice(&(8 + content.len() as u32).to_be_bytes());
+    asset.extend_from_slice(b"uuid");
+    asset.extend_from_slice(&content);
+    asset
+}
+
+fn time(label: &str, expected: &DetectionResult, run: impl Fn() -> DetectionResult) {
+    let start = Date::now();
+    for _ in 0..RUNS {
+        assert_eq!(&run(), expected);
+    }
+    console_log!("{}: {:.1} ms", label, (Date::now() - start) / RUNS as f64);
+}
+
+#[wasm_bindgen_test]
+#[ignore]
+fn bench_input_paths() {
+    let asset = video();
+    let expected = detection::detect(&asset);
+    let array = Uint8Array::from(&asset[..]);
+    drop(asset);
+
+    let buffer: JsValue = array.buffer().into();
+    let shared = SharedArrayBuffer::new(array.length());
+    Uint8Array::new(&shared).set(&array, 0);
+    let shared_view: JsValue = Uint8Array::new_with_byte_offset(&shared, 0).into();
+
+    time("serde_wasm_bindgen ByteBuf", &expected, || {
+        let bytes: serde_bytes::ByteBuf = serde_wasm_bindgen::from_value(buffer.clone()).unwrap();
+        detection::detect(&bytes)
+    });
+    time("bulk copy", &expected, || {
+        detection::detect(&input::to_vec(&buffer).unwrap())
+    });
+    time("chunked ArrayBuffer", &expected, || {
+        input::detect(&buffer).unwrap()
+    });
+    time("chunked SharedArrayBuffer view", &expected, || {
+        input::detect(&shared_view).unwrap()
+    });
+}
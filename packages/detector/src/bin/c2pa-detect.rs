@@ -0,0 +1,116 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Scans files, and every file under directories, for C2PA metadata and prints
+//! one JSON object per file and line.
+//!
+//! Files are read in chunks, seeking past data the scanners skip, so large media
+//! files are cheap to index.
+
+use detector::{detect_reader, scan_all_reader, DetectionResult, ScanReport};
+use serde::Serialize;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "\
+Usage: c2pa-detect [--all] <path>...
+
+Prints a JSON line with the detection result of each file, searching directories
+recursively. With --all, every store and provenance reference is reported.";
+
+#[derive(Serialize)]
+struct Line<'a> {
+    path: &'a str,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Outcome {
+    Result(DetectionResult),
+    Report(ScanReport),
+    Error(String),
+}
+
+fn scan(path: &Path, all: bool) -> io::Result<Outcome> {
+    let mut file = File::open(path)?;
+    if all {
+        Ok(Outcome::Report(scan_all_reader(&mut file)?))
+    } else {
+        Ok(Outcome::Result(detect_reader(&mut file)?))
+    }
+}
+
+/// Calls `visit` with `path` if it is a file, or with every file under it if it is
+/// a directory, in name order and without following symbolic links to directories
+fn walk(path: &Path, visit: &mut impl FnMut(&Path, io::Result<()>)) {
+    let entries = match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::read_dir(path),
+        Ok(_) => return visit(path, Ok(())),
+        Err(err) => return visit(path, Err(err)),
+    };
+    let mut children = match entries.and_then(|entries| entries.collect::<io::Result<Vec<_>>>()) {
+        Ok(entries) => entries,
+        Err(err) => return visit(path, Err(err)),
+    };
+    children.sort_by_key(|entry| entry.file_name());
+    for entry in children {
+        let child = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_symlink() && child.is_dir() => {}
+            Ok(_) => walk(&child, visit),
+            Err(err) => visit(&child, Err(err)),
+        }
+    }
+}
+
+fn main() {
+    let mut all = false;
+    let mut paths = Vec::new();
+    for arg in env::args_os().skip(1) {
+        match arg.to_str() {
+            Some("--all") => all = true,
+            Some("-h") | Some("--help") => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut failed = false;
+    for path in &paths {
+        walk(path, &mut |file, listed| {
+            let outcome = listed
+                .and_then(|_| scan(file, all))
+                .unwrap_or_else(|err| Outcome::Error(err.to_string()));
+            failed |= matches!(outcome, Outcome::Error(_));
+            let line = Line {
+                path: &file.to_string_lossy(),
+                outcome,
+            };
+            let json = serde_json::to_string(&line).expect("results serialize to JSON");
+            if writeln!(out, "{}", json).is_err() {
+                // The reader went away, e.g. the output was piped into `head`
+                process::exit(0);
+            }
+        });
+    }
+    if failed {
+        process::exit(1);
+    }
+}
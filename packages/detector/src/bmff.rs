@@ -0,0 +1,267 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! ISO BMFF (MP4, MOV, HEIF, AVIF) box walker that locates the top-level
+//! `uuid` box carrying a C2PA Manifest Store.
+
+use crate::detection::{Findings, Location};
+use crate::jumbf::{self, BoxHeader};
+use crate::plan::ByteRange;
+use std::convert::TryFrom;
+
+/// Extended type of the C2PA `uuid` box, D8FEC3D6-1B0E-483C-9297-5828877EC481
+pub const C2PA_UUID: [u8; 16] = [
+    0xD8, 0xFE, 0xC3, 0xD6, 0x1B, 0x0E, 0x48, 0x3C, 0x92, 0x97, 0x58, 0x28, 0x87, 0x7E, 0xC4, 0x81,
+];
+
+/// Extended type of the XMP `uuid` box, BE7ACFCB-97A9-42E8-9C71-999491E3AFAC
+const XMP_UUID: [u8; 16] = [
+    0xBE, 0x7A, 0xCF, 0xCB, 0x97, 0xA9, 0x42, 0xE8, 0x9C, 0x71, 0x99, 0x94, 0x91, 0xE3, 0xAF, 0xAC,
+];
+
+const UUID: [u8; 4] = *b"uuid";
+
+const MANIFEST_PURPOSE: &[u8] = b"manifest";
+
+/// Leading box types accepted as BMFF, QuickTime files may omit `ftyp`
+const LEADING_TYPES: [&[u8; 4]; 4] = [b"ftyp", b"moov", b"wide", b"mdat"];
+
+/// Bytes of a C2PA `uuid` box needed to confirm the manifest store header
+const STORE_PEEK_LEN: usize = 256;
+
+/// XMP boxes larger than this are not inspected
+const MAX_XMP_LEN: usize = 1024 * 1024;
+
+/// Largest possible box header: `size`, `type` and `largesize`
+const MAX_HEADER_LEN: usize = 16;
+
+pub fn is_bmff(buf: &[u8]) -> bool {
+    buf.len() >= 8 && LEADING_TYPES.iter().any(|t| &buf[4..8] == *t)
+}
+
+/// Returns the range of the C2PA `uuid` box starting `buf`, located at absolute
+/// offset `offset`, going by its header alone
+///
+/// A box extending to the end of the file, or too large to address, is given a
+/// length of `usize::MAX`.
+pub fn pending_store(buf: &[u8], offset: usize) -> Option<ByteRange> {
+    let header = jumbf::read_box_header(buf)?;
+    let extended_type = buf.get(header.header_len..header.header_len + 16)?;
+    if header.box_type != UUID || extended_type != C2PA_UUID {
+        return None;
+    }
+    let length = header
+        .box_len
+        .map_or(usize::MAX, |len| usize::try_from(len).unwrap_or(usize::MAX));
+    Some(ByteRange::new(offset, length))
+}
+
+/// Resumable walk over the top-level boxes
+///
+/// Box contents, such as a large `mdat`, are skipped without being read, so a
+/// store placed at the end of the file is found while keeping little data around.
+#[derive(Debug, Default)]
+pub struct BmffScanner {
+    /// The store location is that of the whole C2PA `uuid` box
+    pub findings: Findings,
+    /// Absolute offset of the next box
+    pos: usize,
+    finished: bool,
+}
+
+impl BmffScanner {
+    /// Walks the boxes in `buf`, located at absolute offset `base`, and returns
+    /// how many bytes can be dropped, possibly beyond the end of `buf`
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        while !self.finished {
+            // A store box is only settled once it has been seen to its end
+            if self.findings.is_settled() {
+                self.finished = true;
+                break;
+            }
+            let rel = self.pos - base;
+            let available = buf.get(rel..).unwrap_or_default();
+
+            let header = match jumbf::read_box_header(available) {
+                Some(header) => header,
+                None => {
+                    // Either the header is incomplete or its size is invalid
+                    self.finished = eof || available.len() >= MAX_HEADER_LEN;
+                    return rel;
+                }
+            };
+            let box_len = match header.box_len {
+                // A box too large to address runs past the end of the file
+                Some(len) if len >= header.header_len as u64 => usize::try_from(len).ok(),
+                Some(_) => {
+                    self.finished = true;
+                    return rel;
+                }
+                None => None,
+            };
+
+            if header.box_type == UUID {
+                let type_end = header.header_len + 16;
+                if available.len() < type_end && !eof {
+                    return rel;
+                }
+                let to_end = box_len.unwrap_or(usize::MAX);
+                let needed = match available.get(header.header_len..type_end) {
+                    Some(extended_type) if extended_type == C2PA_UUID => to_end.min(STORE_PEEK_LEN),
+                    Some(extended_type) if extended_type == XMP_UUID && to_end <= MAX_XMP_LEN => {
+                        to_end
+                    }
+                    _ => 0,
+                };
+                if available.len() < needed && !eof {
+                    return rel;
+                }
+                if needed > 0 {
+                    let data = &available[..available.len().min(to_end)];
+                    self.inspect_uuid(&header, box_len, data);
+                }
+            }
+
+            match box_len {
+                Some(len) => match self.pos.checked_add(len) {
+                    Some(next) => self.pos = next,
+                    None => self.finished = true,
+                },
+                // The box extends to the end of the file
+                None => self.finished = true,
+            }
+        }
+        self.pos - base
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn inspect_uuid(&mut self, header: &BoxHeader, box_len: Option<usize>, data: &[u8]) {
+        let content = &data[header.header_len.min(data.len())..];
+        if content.len() < 16 {
+            return;
+        }
+        let (extended_type, content) = content.split_at(16);
+
+        if extended_type == C2PA_UUID {
+            if let Some(store) = manifest_data(content) {
+                if jumbf::is_c2pa_store(store) {
+                    self.findings.add_store(Location {
+                        offset: self.pos,
+                        length: box_len,
+                    });
+                    self.finished = self.findings.is_settled();
+                }
+            }
+        } else if extended_type == XMP_UUID {
+            self.findings
+                .add_xmp(content, self.pos + header.header_len + 16);
+        }
+    }
+}
+
+/// Returns the manifest store held by the C2PA `uuid` box at `location`
+pub fn store_data(buf: &[u8], location: Location) -> Option<&[u8]> {
+    let data = buf.get(location.offset..)?;
+    let data = match location.length {
+        Some(len) => data.get(..len)?,
+        None => data,
+    };
+    let header = jumbf::read_box_header(data)?;
+    manifest_data(data.get(header.header_len + 16..)?)
+}
+
+/// Returns the JUMBF data of a C2PA `uuid` box with the `manifest` purpose,
+/// given the box content following its extended type
+fn manifest_data(content: &[u8]) -> Option<&[u8]> {
+    // FullBox version and flags
+    let content = content.get(4..)?;
+    let purpose_end = content.iter().position(|&b| b == 0)?;
+    if &content[..purpose_end] != MANIFEST_PURPOSE {
+        return None;
+    }
+    // Skip the terminator and the 64-bit offset to the auxiliary `uuid` box
+    content.get(purpose_end + 1 + 8..)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jumbf::CAI_BLOCK_UUID;
+
+    fn manifest_box() -> Vec<u8> {
+        let mut jumd = 30u32.to_be_bytes().to_vec();
+        jumd.extend_from_slice(b"jumd");
+        jumd.extend_from_slice(&CAI_BLOCK_UUID);
+        jumd.extend_from_slice(b"\x03c2pa\0");
+
+        let mut content = C2PA_UUID.to_vec();
+        content.extend_from_slice(&[0; 4]);
+        content.extend_from_slice(b"manifest\0");
+        content.extend_from_slice(&[0; 8]);
+        content.extend_from_slice(&(8 + jumd.len() as u32).to_be_bytes());
+        content.extend_from_slice(b"jumb");
+        content.extend_from_slice(&jumd);
+
+        let mut uuid_box = (8 + content.len() as u32).to_be_bytes().to_vec();
+        uuid_box.extend_from_slice(b"uuid");
+        uuid_box.extend_from_slice(&content);
+        uuid_box
+    }
+
+    #[test]
+    fn reports_store_with_largesize() {
+        let mut asset = b"\0\0\0\x10ftypisom\0\0\0\0".to_vec();
+        let uuid_box = manifest_box();
+        // Declare a 64-bit largesize beyond what a 32-bit target can address
+        let largesize = (1u64 << 32) + uuid_box.len() as u64;
+        asset.extend_from_slice(&[0, 0, 0, 1]);
+        asset.extend_from_slice(b"uuid");
+        asset.extend_from_slice(&largesize.to_be_bytes());
+        asset.extend_from_slice(&uuid_box[8..]);
+
+        let mut scanner = BmffScanner::default();
+        scanner.scan(&asset, 0, true);
+
+        assert_eq!(
+            scanner.findings.store(),
+            Some(Location {
+                offset: 16,
+                length: usize::try_from(largesize).ok(),
+            })
+        );
+        assert_eq!(
+            pending_store(&asset[16..], 16).map(|range| range.length),
+            Some(usize::try_from(largesize).unwrap_or(usize::MAX))
+        );
+    }
+
+    #[test]
+    fn finds_store_after_large_mdat() {
+        let mut asset = b"\0\0\0\x10ftypisom\0\0\0\0".to_vec();
+        // `mdat` using a 64-bit largesize
+        asset.extend_from_slice(&[0, 0, 0, 1]);
+        asset.extend_from_slice(b"mdat");
+        asset.extend_from_slice(&(16u64 + 100_000).to_be_bytes());
+        asset.extend(vec![0; 100_000]);
+        let uuid_box = manifest_box();
+        asset.extend_from_slice(&uuid_box);
+
+        let mut scanner = BmffScanner::default();
+        scanner.scan(&asset, 0, true);
+
+        assert_eq!(
+            scanner.findings.store(),
+            Some(Location {
+                offset: 100_032,
+                length: Some(uuid_box.len()),
+            })
+        );
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Minimal CBOR (RFC 8949) decoder for reading claims and COSE signatures.
+
+use std::convert::TryFrom;
+
+/// Nesting beyond this is treated as malformed
+const MAX_DEPTH: usize = 64;
+
+/// Additional information value announcing an indefinite length
+const INDEFINITE: u8 = 31;
+const BREAK: u8 = 0xFF;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unsigned(u64),
+    Negative(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Tag(u64, Box<Value>),
+    Bool(bool),
+    Null,
+    Undefined,
+    Float(f64),
+    Simple(u8),
+}
+
+impl Value {
+    /// Looks up a text key in a map
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entry(|k| matches!(k, Value::Text(text) if text == key))
+    }
+
+    /// Looks up an integer key in a map, as used by COSE headers
+    pub fn get_int(&self, key: u64) -> Option<&Value> {
+        self.entry(|k| *k == Value::Unsigned(key))
+    }
+
+    fn entry(&self, matches: impl Fn(&Value) -> bool) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(key, _)| matches(key))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// The value inside any tags
+    pub fn untagged(&self) -> &Value {
+        match self {
+            Value::Tag(_, value) => value.untagged(),
+            value => value,
+        }
+    }
+}
+
+/// Decodes the data item at the start of `buf`, ignoring anything after it
+pub fn decode(buf: &[u8]) -> Option<Value> {
+    Decoder { buf, pos: 0 }.value(0)
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let b = *self.buf.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn take(&mut self, len: u64) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(usize::try_from(len).ok()?)?;
+        let bytes = self.buf.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    fn uint(&mut self, len: u64) -> Option<u64> {
+        Some(
+            self.take(len)?
+                .iter()
+                .fold(0u64, |value, &b| value << 8 | b as u64),
+        )
+    }
+
+    /// Reads the argument following the initial byte, `None` for an indefinite length
+    fn argument(&mut self, info: u8) -> Option<Option<u64>> {
+        match info {
+            0..=23 => Some(Some(info as u64)),
+            24 => self.uint(1).map(Some),
+            25 => self.uint(2).map(Some),
+            26 => self.uint(4).map(Some),
+            27 => self.uint(8).map(Some),
+            INDEFINITE => Some(None),
+            _ => None,
+        }
+    }
+
+    /// Items of a definite length container, capped by what the buffer could hold
+    fn capacity(&self, count: u64) -> usize {
+        (count as usize).min(self.buf.len() - self.pos)
+    }
+
+    fn at_break(&mut self) -> bool {
+        if self.buf.get(self.pos) == Some(&BREAK) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let initial = self.byte()?;
+        let major = initial >> 5;
+        let info = initial & 0x1F;
+
+        if major == 7 {
+            return self.simple(info);
+        }
+        let argument = self.argument(info)?;
+        match (major, argument) {
+            (0, Some(value)) => Some(Value::Unsigned(value)),
+            (1, Some(value)) => Some(Value::Negative(-1 - value as i128)),
+            (2, _) | (3, _) => {
+                let bytes = match argument {
+                    Some(len) => self.take(len)?.to_vec(),
+                    None => {
+                        // Concatenated definite length chunks of the same type
+                        let mut bytes = Vec::new();
+                        while !self.at_break() {
+                            match self.value(depth + 1)? {
+                                Value::Bytes(chunk) if major == 2 => bytes.extend(chunk),
+                                Value::Text(chunk) if major == 3 => {
+                                    bytes.extend(chunk.into_bytes())
+                                }
+                                _ => return None,
+                            }
+                        }
+                        bytes
+                    }
+                };
+                if major == 2 {
+                    Some(Value::Bytes(bytes))
+                } else {
+                    String::from_utf8(bytes).ok().map(Value::Text)
+                }
+            }
+            (4, Some(count)) => {
+                let mut items = Vec::with_capacity(self.capacity(count));
+                for _ in 0..count {
+                    items.push(self.value(depth + 1)?);
+                }
+                Some(Value::Array(items))
+            }
+            (4, None) => {
+                let mut items = Vec::new();
+                while !self.at_break() {
+                    items.push(self.value(depth + 1)?);
+                }
+                Some(Value::Array(items))
+            }
+            (5, Some(count)) => {
+                let mut entries = Vec::with_capacity(self.capacity(count));
+                for _ in 0..count {
+                    entries.push((self.value(depth + 1)?, self.value(depth + 1)?));
+                }
+                Some(Value::Map(entries))
+            }
+            (5, None) => {
+                let mut entries = Vec::new();
+                while !self.at_break() {
+                    entries.push((self.value(depth + 1)?, self.value(depth + 1)?));
+                }
+                Some(Value::Map(entries))
+            }
+            (6, Some(tag)) => Some(Value::Tag(tag, Box::new(self.value(depth + 1)?))),
+            _ => None,
+        }
+    }
+
+    fn simple(&mut self, info: u8) -> Option<Value> {
+        match info {
+            20 => Some(Value::Bool(false)),
+            21 => Some(Value::Bool(true)),
+            22 => Some(Value::Null),
+            23 => Some(Value::Undefined),
+            24 => self.byte().map(Value::Simple),
+            25 => self
+                .uint(2)
+                .map(|half| Value::Float(half_to_f64(half as u16))),
+            26 => self
+                .uint(4)
+                .map(|bits| Value::Float(f32::from_bits(bits as u32) as f64)),
+            27 => self.uint(8).map(|bits| Value::Float(f64::from_bits(bits))),
+            0..=19 => Some(Value::Simple(info)),
+            _ => None,
+        }
+    }
+}
+
+fn half_to_f64(half: u16) -> f64 {
+    let exponent = (half >> 10) & 0x1F;
+    let mantissa = (half & 0x3FF) as f64;
+    let magnitude = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        0x1F if mantissa == 0.0 => f64::INFINITY,
+        0x1F => f64::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent as i32 - 15),
+    };
+    if half & 0x8000 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_nested_items() {
+        // {"a": [1, -2, h'0102'], "b": _ "xy"}
+        let data = [
+            0xA2, 0x61, b'a', 0x83, 0x01, 0x21, 0x42, 0x01, 0x02, 0x61, b'b', 0x7F, 0x61, b'x',
+            0x61, b'y', 0xFF,
+        ];
+
+        let value = decode(&data).unwrap();
+        assert_eq!(
+            value.get("a"),
+            Some(&Value::Array(vec![
+                Value::Unsigned(1),
+                Value::Negative(-2),
+                Value::Bytes(vec![1, 2]),
+            ]))
+        );
+        assert_eq!(value.get("b").and_then(Value::as_text), Some("xy"));
+    }
+
+    #[test]
+    fn rejects_truncated_items() {
+        assert_eq!(decode(&[0x82, 0x01]), None);
+        assert_eq!(decode(&[0x5A, 0xFF, 0xFF, 0xFF, 0xFF]), None);
+    }
+}
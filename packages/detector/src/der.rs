@@ -0,0 +1,82 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Minimal DER reader for picking fields out of certificates and timestamps.
+
+use std::convert::TryFrom;
+
+pub const OCTET_STRING: u8 = 0x04;
+pub const GENERALIZED_TIME: u8 = 0x18;
+/// `[0]`, used for the certificate version and the CMS content
+pub const CONTEXT_0: u8 = 0xA0;
+
+const CONSTRUCTED: u8 = 0x20;
+
+/// Nesting beyond this is not searched
+const MAX_DEPTH: usize = 16;
+
+/// A tag-length-value element
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Element<'a> {
+    /// The identifier octet, multi-byte tag numbers are not supported
+    pub tag: u8,
+    pub content: &'a [u8],
+}
+
+/// Reads the element at the start of `buf`, returning it with the bytes after it
+pub fn read(buf: &[u8]) -> Option<(Element<'_>, &[u8])> {
+    let tag = *buf.first()?;
+    if tag & 0x1F == 0x1F {
+        return None;
+    }
+    let first = *buf.get(1)?;
+    let (len, header_len) = if first < 0x80 {
+        (first as usize, 2)
+    } else {
+        let count = (first & 0x7F) as usize;
+        if count == 0 || count > 4 {
+            // Indefinite or implausibly long lengths
+            return None;
+        }
+        let bytes = buf.get(2..2 + count)?;
+        let len = bytes.iter().fold(0u32, |len, &b| len << 8 | b as u32);
+        (usize::try_from(len).ok()?, 2 + count)
+    };
+    let content = buf.get(header_len..header_len.checked_add(len)?)?;
+    Some((Element { tag, content }, &buf[header_len + len..]))
+}
+
+/// The elements inside a constructed element's content, stopping at the first malformed one
+pub fn children(content: &[u8]) -> impl Iterator<Item = Element<'_>> {
+    let mut rest = content;
+    std::iter::from_fn(move || {
+        let (element, next) = read(rest)?;
+        rest = next;
+        Some(element)
+    })
+}
+
+/// Depth-first search for the first element with `tag`, also looking into
+/// octet strings that wrap DER, as CMS does for its content
+pub fn find(content: &[u8], tag: u8) -> Option<Element<'_>> {
+    find_within(content, tag, 0)
+}
+
+fn find_within(content: &[u8], tag: u8, depth: usize) -> Option<Element<'_>> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    children(content).find_map(|element| {
+        if element.tag == tag {
+            Some(element)
+        } else if element.tag & CONSTRUCTED != 0 || element.tag == OCTET_STRING {
+            find_within(element.content, tag, depth + 1)
+        } else {
+            None
+        }
+    })
+}
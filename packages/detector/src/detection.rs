@@ -0,0 +1,689 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+use crate::bmff::{self, BmffScanner};
+use crate::flac::{self, FlacScanner};
+use crate::gif::{self, GifScanner};
+use crate::jpeg::{self, JpegScanner};
+use crate::jumbf::CAI_BLOCK_UUID;
+use crate::jxl::{self, JxlScanner};
+use crate::mp3::{self, Mp3Scanner};
+use crate::pdf::{self, PdfScanner};
+use crate::plan;
+use crate::png::{self, PngScanner};
+use crate::riff::{self, RiffScanner};
+use crate::tiff::{self, TiffScanner};
+use crate::xml::{self, XmlScanner};
+use crate::xmp::{self, Provenance, Search};
+use crate::zip::{self, ZipScanner};
+use serde::Serialize;
+use twoway::find_bytes;
+
+/// Container format the scanned bytes were recognised as
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Format {
+    Jpeg,
+    Png,
+    Bmff,
+    Riff,
+    Pdf,
+    Tiff,
+    Gif,
+    Jxl,
+    Mp3,
+    Flac,
+    Xml,
+    Zip,
+    Unknown,
+}
+
+/// Outcome of scanning an asset for C2PA metadata
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum DetectionResult {
+    /// An embedded JUMBF manifest store
+    ManifestStore {
+        offset: usize,
+        /// Size of the container structure holding the store, when it is a single unit
+        #[serde(skip_serializing_if = "Option::is_none")]
+        length: Option<usize>,
+        format: Format,
+    },
+    /// A manifest store is present but cannot be read as-is
+    Damaged {
+        offset: usize,
+        format: Format,
+        damage: Damage,
+    },
+    /// An XMP `dcterms:provenance` reference to a remote manifest
+    RemoteReference {
+        offset: usize,
+        format: Format,
+        /// The remote manifest URL, if it could be read from the XMP packet
+        #[serde(skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    /// No C2PA metadata was found
+    NotPresent { format: Format },
+    /// The asset is a bare codestream, without a container that could hold a manifest store
+    NoContainer { format: Format },
+}
+
+/// Why an embedded manifest store was reported as damaged
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Damage {
+    /// The container checksum does not match its contents
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// An odd-length chunk is not followed by its pad byte
+    MissingPadding,
+    /// The segment, box or chunk holding the store runs past the end of the file
+    /// or of its container
+    Truncated {
+        /// What is cut off, e.g. `caBX` or `jumb`
+        container: String,
+        expected: usize,
+        available: usize,
+    },
+    /// The APP11 segments carrying the store skip packet `sequence`, leaving
+    /// fewer bytes of its superbox than declared
+    MissingSegment {
+        sequence: u32,
+        expected: usize,
+        available: usize,
+    },
+}
+
+/// Location of a manifest store within the asset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub offset: usize,
+    pub length: Option<usize>,
+}
+
+/// A single piece of C2PA metadata found in the asset
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Occurrence {
+    /// An embedded JUMBF manifest store, located by its container structure
+    ManifestStore {
+        offset: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        length: Option<usize>,
+    },
+    /// An embedded manifest store that cannot be read as-is
+    Damaged { offset: usize, damage: Damage },
+    /// An XMP provenance reference to a remote manifest
+    RemoteReference {
+        offset: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+}
+
+impl Occurrence {
+    pub fn offset(&self) -> usize {
+        match self {
+            Occurrence::ManifestStore { offset, .. }
+            | Occurrence::Damaged { offset, .. }
+            | Occurrence::RemoteReference { offset, .. } => *offset,
+        }
+    }
+
+    /// Whether this is an embedded store, damaged or not
+    pub fn is_store(&self) -> bool {
+        !matches!(self, Occurrence::RemoteReference { .. })
+    }
+}
+
+/// Every occurrence of C2PA metadata in an asset
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanReport {
+    pub format: Format,
+    /// Occurrences in file order
+    pub occurrences: Vec<Occurrence>,
+    /// More than one embedded store is present, which fails validation
+    pub multiple_stores: bool,
+}
+
+/// What a scanner has found so far
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Findings {
+    /// Occurrences in the order they were found
+    pub occurrences: Vec<Occurrence>,
+    /// Keep scanning after the first store so that every occurrence is reported
+    pub exhaustive: bool,
+    /// Number of leading bytes of the asset made available to the scanner so far
+    pub seen: usize,
+}
+
+impl Findings {
+    pub fn add_store(&mut self, location: Location) {
+        if !self.contains_store(location.offset) {
+            self.occurrences.push(Occurrence::ManifestStore {
+                offset: location.offset,
+                length: location.length,
+            });
+        }
+    }
+
+    /// Records a damaged store, replacing a store previously found at the same offset
+    pub fn add_damage(&mut self, offset: usize, damage: Damage) {
+        self.occurrences
+            .retain(|occurrence| !(occurrence.is_store() && occurrence.offset() == offset));
+        self.occurrences
+            .push(Occurrence::Damaged { offset, damage });
+    }
+
+    pub fn add_provenance(&mut self, provenance: Provenance) {
+        let found = self
+            .occurrences
+            .iter()
+            .any(|occurrence| !occurrence.is_store() && occurrence.offset() == provenance.offset);
+        if !found {
+            self.occurrences.push(Occurrence::RemoteReference {
+                offset: provenance.offset,
+                url: provenance.url,
+            });
+        }
+    }
+
+    /// Records the provenance references of a complete XMP packet located at `offset`
+    pub fn add_xmp(&mut self, packet: &[u8], offset: usize) {
+        if !self.wants_provenance() {
+            return;
+        }
+        let found = if self.exhaustive {
+            xmp::find_all_provenance(packet)
+        } else {
+            match xmp::find_provenance(packet, true) {
+                Search::Found(provenance) => vec![provenance],
+                _ => Vec::new(),
+            }
+        };
+        for provenance in found {
+            self.add_provenance(Provenance {
+                offset: offset + provenance.offset,
+                ..provenance
+            });
+        }
+    }
+
+    pub fn contains_store(&self, offset: usize) -> bool {
+        self.occurrences
+            .iter()
+            .any(|occurrence| occurrence.is_store() && occurrence.offset() == offset)
+    }
+
+    /// The first intact store
+    pub fn store(&self) -> Option<Location> {
+        self.occurrences
+            .iter()
+            .find_map(|occurrence| match occurrence {
+                Occurrence::ManifestStore { offset, length } => Some(Location {
+                    offset: *offset,
+                    length: *length,
+                }),
+                _ => None,
+            })
+    }
+
+    /// The first damaged store
+    pub fn damage(&self) -> Option<(usize, Damage)> {
+        self.occurrences
+            .iter()
+            .find_map(|occurrence| match occurrence {
+                Occurrence::Damaged { offset, damage } => Some((*offset, damage.clone())),
+                _ => None,
+            })
+    }
+
+    /// The first provenance reference
+    pub fn provenance(&self) -> Option<Provenance> {
+        self.occurrences
+            .iter()
+            .find_map(|occurrence| match occurrence {
+                Occurrence::RemoteReference { offset, url } => Some(Provenance {
+                    offset: *offset,
+                    url: url.clone(),
+                }),
+                _ => None,
+            })
+    }
+
+    /// Whether scanning can stop, as a store was found and not every occurrence is wanted
+    ///
+    /// A store whose container runs past the bytes seen so far may still turn
+    /// out to be truncated, so it does not settle the result.
+    pub fn is_settled(&self) -> bool {
+        !self.exhaustive
+            && self.occurrences.iter().any(|occurrence| match occurrence {
+                Occurrence::ManifestStore {
+                    offset,
+                    length: Some(length),
+                } => offset.saturating_add(*length) <= self.seen,
+                occurrence => occurrence.is_store(),
+            })
+    }
+
+    /// Marks the stores whose `container` runs past the end of the file as damaged,
+    /// once the whole file has been seen
+    pub fn mark_truncated(&mut self, container: &str) {
+        let seen = self.seen;
+        let truncated: Vec<(usize, usize)> = self
+            .occurrences
+            .iter()
+            .filter_map(|occurrence| match occurrence {
+                Occurrence::ManifestStore {
+                    offset,
+                    length: Some(length),
+                } if offset.saturating_add(*length) > seen => Some((*offset, *length)),
+                _ => None,
+            })
+            .collect();
+        for (offset, expected) in truncated {
+            self.add_damage(
+                offset,
+                Damage::Truncated {
+                    container: container.to_string(),
+                    expected,
+                    available: seen.saturating_sub(offset),
+                },
+            );
+        }
+    }
+
+    /// Whether provenance references should still be looked for
+    pub fn wants_provenance(&self) -> bool {
+        self.exhaustive || self.provenance().is_none()
+    }
+
+    pub fn result(&self, format: Format) -> DetectionResult {
+        if let Some(Location { offset, length }) = self.store() {
+            DetectionResult::ManifestStore {
+                offset,
+                length,
+                format,
+            }
+        } else if let Some((offset, damage)) = self.damage() {
+            DetectionResult::Damaged {
+                offset,
+                format,
+                damage,
+            }
+        } else if let Some(provenance) = self.provenance() {
+            DetectionResult::RemoteReference {
+                offset: provenance.offset,
+                format,
+                url: provenance.url,
+            }
+        } else {
+            DetectionResult::NotPresent { format }
+        }
+    }
+
+    pub fn report(&self, format: Format) -> ScanReport {
+        let mut occurrences = self.occurrences.clone();
+        occurrences.sort_by_key(Occurrence::offset);
+        let stores = occurrences.iter().filter(|o| o.is_store()).count();
+        ScanReport {
+            format,
+            occurrences,
+            multiple_stores: stores > 1,
+        }
+    }
+}
+
+/// Blind search for the store UUID and provenance reference in formats we cannot walk
+#[derive(Debug, Default)]
+pub struct GenericScanner {
+    pub findings: Findings,
+}
+
+impl GenericScanner {
+    /// Bytes kept between calls so that a marker split across chunks is still found,
+    /// along with the byte preceding a property name
+    const OVERLAP: usize = xmp::DCTERMS_PROVENANCE.len();
+
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        let mut from = 0;
+        while let Some(pos) = find_bytes(&buf[from..], &CAI_BLOCK_UUID) {
+            self.findings.add_store(Location {
+                offset: base + from + pos,
+                length: None,
+            });
+            if self.findings.is_settled() {
+                return buf.len();
+            }
+            from += pos + CAI_BLOCK_UUID.len();
+        }
+        let mut keep_from = if eof {
+            buf.len()
+        } else {
+            buf.len().saturating_sub(Self::OVERLAP)
+        };
+        let mut from = 0;
+        while self.findings.wants_provenance() {
+            match xmp::find_provenance(&buf[from..], eof) {
+                Search::Found(provenance) => {
+                    let pos = from + provenance.offset;
+                    self.findings.add_provenance(Provenance {
+                        offset: base + pos,
+                        ..provenance
+                    });
+                    from = pos + 1;
+                }
+                // Hold on to the reference until its value has arrived
+                Search::Incomplete(pos) => {
+                    keep_from = keep_from.min((from + pos).saturating_sub(1));
+                    break;
+                }
+                Search::NotFound => break,
+            }
+        }
+        keep_from
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.findings.is_settled()
+    }
+}
+
+/// Format specific scanner, chosen from the first bytes of the asset
+#[derive(Debug)]
+pub enum Scanner {
+    Jpeg(JpegScanner),
+    Png(PngScanner),
+    Bmff(BmffScanner),
+    Riff(RiffScanner),
+    Pdf(PdfScanner),
+    Tiff(TiffScanner),
+    Gif(GifScanner),
+    Jxl(JxlScanner),
+    Mp3(Mp3Scanner),
+    Flac(FlacScanner),
+    Xml(XmlScanner),
+    Zip(ZipScanner),
+    Generic(GenericScanner),
+}
+
+impl Scanner {
+    /// Number of leading bytes needed to pick a scanner
+    pub const HEADER_LEN: usize = 8;
+
+    pub fn for_header(header: &[u8]) -> Self {
+        if jpeg::is_jpeg(header) {
+            Scanner::Jpeg(JpegScanner::default())
+        } else if png::is_png(header) {
+            Scanner::Png(PngScanner::default())
+        } else if jxl::is_jxl(header) {
+            Scanner::Jxl(JxlScanner::default())
+        } else if bmff::is_bmff(header) {
+            Scanner::Bmff(BmffScanner::default())
+        } else if riff::is_riff(header) {
+            Scanner::Riff(RiffScanner::default())
+        } else if pdf::is_pdf(header) {
+            Scanner::Pdf(PdfScanner::default())
+        } else if tiff::is_tiff(header) {
+            Scanner::Tiff(TiffScanner::default())
+        } else if gif::is_gif(header) {
+            Scanner::Gif(GifScanner::default())
+        } else if mp3::is_mp3(header) {
+            Scanner::Mp3(Mp3Scanner::default())
+        } else if flac::is_flac(header) {
+            Scanner::Flac(FlacScanner::default())
+        } else if zip::is_zip(header) {
+            Scanner::Zip(ZipScanner::default())
+        } else if xml::is_xml(header) {
+            Scanner::Xml(XmlScanner::default())
+        } else {
+            Scanner::Generic(GenericScanner::default())
+        }
+    }
+
+    /// Scans `buf`, located at absolute offset `base`, and returns how many bytes
+    /// no longer need to be kept around
+    ///
+    /// This may exceed `buf.len()` when the scanner wants to skip over data that
+    /// has not arrived yet.
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        let findings = self.findings_mut();
+        findings.seen = findings.seen.max(base + buf.len());
+        let consumed = match self {
+            Scanner::Jpeg(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Png(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Bmff(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Riff(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Pdf(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Tiff(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Gif(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Jxl(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Mp3(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Flac(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Xml(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Zip(scanner) => scanner.scan(buf, base, eof),
+            Scanner::Generic(scanner) => scanner.scan(buf, base, eof),
+        };
+        if eof {
+            let container = self.store_container();
+            self.findings_mut().mark_truncated(container);
+        }
+        consumed
+    }
+
+    /// Whether the result is settled and no further input can change it
+    pub fn is_finished(&self) -> bool {
+        match self {
+            Scanner::Jpeg(scanner) => scanner.is_finished(),
+            Scanner::Png(scanner) => scanner.is_finished(),
+            Scanner::Bmff(scanner) => scanner.is_finished(),
+            Scanner::Riff(scanner) => scanner.is_finished(),
+            Scanner::Pdf(scanner) => scanner.is_finished(),
+            Scanner::Tiff(scanner) => scanner.is_finished(),
+            Scanner::Gif(scanner) => scanner.is_finished(),
+            Scanner::Jxl(scanner) => scanner.is_finished(),
+            Scanner::Mp3(scanner) => scanner.is_finished(),
+            Scanner::Flac(scanner) => scanner.is_finished(),
+            Scanner::Xml(scanner) => scanner.is_finished(),
+            Scanner::Zip(scanner) => scanner.is_finished(),
+            Scanner::Generic(scanner) => scanner.is_finished(),
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        match self {
+            Scanner::Jpeg(_) => Format::Jpeg,
+            Scanner::Png(_) => Format::Png,
+            Scanner::Bmff(_) => Format::Bmff,
+            Scanner::Riff(_) => Format::Riff,
+            Scanner::Pdf(_) => Format::Pdf,
+            Scanner::Tiff(_) => Format::Tiff,
+            Scanner::Gif(_) => Format::Gif,
+            Scanner::Jxl(_) => Format::Jxl,
+            Scanner::Mp3(_) => Format::Mp3,
+            Scanner::Flac(_) => Format::Flac,
+            Scanner::Xml(_) => Format::Xml,
+            Scanner::Zip(_) => Format::Zip,
+            Scanner::Generic(_) => Format::Unknown,
+        }
+    }
+
+    /// Name of the structure holding a store of known length
+    fn store_container(&self) -> &'static str {
+        match self {
+            Scanner::Jpeg(_) => "APP11",
+            Scanner::Png(_) => "caBX",
+            Scanner::Bmff(_) => "uuid",
+            Scanner::Riff(_) => "C2PA",
+            Scanner::Pdf(_) => "stream",
+            Scanner::Tiff(_) => "tag",
+            Scanner::Gif(_) => "C2PA_GIF",
+            Scanner::Jxl(_) => "jumb",
+            Scanner::Mp3(_) => "GEOB",
+            Scanner::Flac(_) => "APPLICATION",
+            Scanner::Xml(_) => "c2pa:manifest",
+            Scanner::Zip(_) => "local file",
+            Scanner::Generic(_) => "jumb",
+        }
+    }
+
+    pub fn findings(&self) -> &Findings {
+        match self {
+            Scanner::Jpeg(scanner) => &scanner.findings,
+            Scanner::Png(scanner) => &scanner.findings,
+            Scanner::Bmff(scanner) => &scanner.findings,
+            Scanner::Riff(scanner) => &scanner.findings,
+            Scanner::Pdf(scanner) => &scanner.findings,
+            Scanner::Tiff(scanner) => &scanner.findings,
+            Scanner::Gif(scanner) => &scanner.findings,
+            Scanner::Jxl(scanner) => &scanner.findings,
+            Scanner::Mp3(scanner) => &scanner.findings,
+            Scanner::Flac(scanner) => &scanner.findings,
+            Scanner::Xml(scanner) => &scanner.findings,
+            Scanner::Zip(scanner) => &scanner.findings,
+            Scanner::Generic(scanner) => &scanner.findings,
+        }
+    }
+
+    pub fn findings_mut(&mut self) -> &mut Findings {
+        match self {
+            Scanner::Jpeg(scanner) => &mut scanner.findings,
+            Scanner::Png(scanner) => &mut scanner.findings,
+            Scanner::Bmff(scanner) => &mut scanner.findings,
+            Scanner::Riff(scanner) => &mut scanner.findings,
+            Scanner::Pdf(scanner) => &mut scanner.findings,
+            Scanner::Tiff(scanner) => &mut scanner.findings,
+            Scanner::Gif(scanner) => &mut scanner.findings,
+            Scanner::Jxl(scanner) => &mut scanner.findings,
+            Scanner::Mp3(scanner) => &mut scanner.findings,
+            Scanner::Flac(scanner) => &mut scanner.findings,
+            Scanner::Xml(scanner) => &mut scanner.findings,
+            Scanner::Zip(scanner) => &mut scanner.findings,
+            Scanner::Generic(scanner) => &mut scanner.findings,
+        }
+    }
+
+    pub fn result(&self) -> DetectionResult {
+        match self {
+            Scanner::Jxl(scanner) if scanner.is_codestream() => DetectionResult::NoContainer {
+                format: Format::Jxl,
+            },
+            scanner => scanner.findings().result(scanner.format()),
+        }
+    }
+
+    pub fn report(&self) -> ScanReport {
+        self.findings().report(self.format())
+    }
+}
+
+/// Scans `buf` for an embedded manifest store, falling back to an XMP provenance reference
+pub fn detect(buf: &[u8]) -> DetectionResult {
+    let mut scanner = Scanner::for_header(buf);
+    scanner.scan(buf, 0, true);
+    scanner.result()
+}
+
+/// Same as `detect` for `buf` holding only the first bytes of the asset
+///
+/// A store running past the end of `buf` is reported as found rather than as
+/// truncated, and formats read from the end of the file, such as PDF and ZIP,
+/// report nothing.
+pub fn detect_head(buf: &[u8]) -> DetectionResult {
+    let mut scanner = Scanner::for_header(buf);
+    let next = scanner.scan(buf, 0, false);
+    let result = scanner.result();
+    let findings = scanner.findings();
+    if scanner.is_finished() || findings.store().is_some() || findings.damage().is_some() {
+        return result;
+    }
+    // The scanner may have stopped at the store, waiting for the rest of it
+    match plan::pending_store(&scanner, buf, next) {
+        Some(range) => DetectionResult::ManifestStore {
+            offset: range.offset,
+            length: Some(range.length).filter(|&length| length != usize::MAX),
+            format: scanner.format(),
+        },
+        None => result,
+    }
+}
+
+/// Scans the whole of `buf` and reports every store and provenance reference found
+pub fn scan_all(buf: &[u8]) -> ScanReport {
+    let mut scanner = Scanner::for_header(buf);
+    scanner.findings_mut().exhaustive = true;
+    scanner.scan(buf, 0, true);
+    scanner.report()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_duplicate_stores() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/c2pa-actions-1.2.png");
+        // Repeat the `caBX` chunk, as a bad re-save might
+        let store = &asset[33..33 + 73904];
+        let mut asset = asset.to_vec();
+        asset.splice(33..33, store.iter().copied());
+
+        let report = scan_all(&asset);
+        assert!(report.multiple_stores);
+        assert_eq!(
+            &report.occurrences[..2],
+            &[
+                Occurrence::ManifestStore {
+                    offset: 33,
+                    length: Some(73904),
+                },
+                Occurrence::ManifestStore {
+                    offset: 33 + 73904,
+                    length: Some(73904),
+                },
+            ]
+        );
+        assert!(matches!(
+            report.occurrences[2],
+            Occurrence::RemoteReference { .. }
+        ));
+    }
+
+    #[test]
+    fn reports_truncated_chunk() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/c2pa-actions-1.2.png");
+
+        assert_eq!(
+            detect(&asset[..1000]),
+            DetectionResult::Damaged {
+                offset: 33,
+                format: Format::Png,
+                damage: Damage::Truncated {
+                    container: "caBX".to_string(),
+                    expected: 73904,
+                    available: 1000 - 33,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn finds_store_running_past_the_head() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/c2pa-actions-1.2.png");
+
+        assert_eq!(
+            detect_head(&asset[..1000]),
+            DetectionResult::ManifestStore {
+                offset: 33,
+                length: Some(73904),
+                format: Format::Png,
+            }
+        );
+    }
+}
@@ -0,0 +1,93 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Extraction of the raw JUMBF manifest store from an asset, without any
+//! verification, so that it can be archived apart from the asset.
+
+use crate::detection::{Format, Location, Scanner};
+use crate::{bmff, flac, gif, jpeg, jumbf, jxl, mp3, pdf, png, riff, tiff, xml, zip};
+use serde::Serialize;
+use std::convert::TryFrom;
+
+/// A manifest store taken out of its container
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedStore {
+    /// Container format the store came from
+    pub format: Format,
+    #[serde(with = "serde_bytes")]
+    pub bytes: Vec<u8>,
+}
+
+/// Returns the bytes of the first manifest store embedded in `buf`
+///
+/// Stores split across several container segments are reassembled. Nothing is
+/// returned when the store cannot be read as-is, e.g. when it is damaged or
+/// compressed within a PDF or ZIP archive.
+pub fn extract_store(buf: &[u8]) -> Option<ExtractedStore> {
+    let mut scanner = Scanner::for_header(buf);
+    scanner.scan(buf, 0, true);
+    let format = scanner.format();
+    let location = scanner.findings().store()?;
+
+    let bytes = match format {
+        Format::Jpeg => jpeg::store_data(buf, location.offset)?,
+        Format::Png => png::store_data(buf, location)?.to_vec(),
+        Format::Bmff => bmff::store_data(buf, location)?.to_vec(),
+        Format::Riff => riff::store_data(buf, location)?.to_vec(),
+        Format::Pdf => pdf::store_data(buf, location)?.to_vec(),
+        Format::Tiff => tiff::store_data(buf, location)?.to_vec(),
+        Format::Gif => gif::store_data(buf, location)?,
+        Format::Jxl => jxl::store_data(buf, location)?.to_vec(),
+        Format::Mp3 => mp3::store_data(buf, location)?,
+        Format::Flac => flac::store_data(buf, location)?.to_vec(),
+        Format::Xml => xml::store_data(buf, location)?,
+        Format::Zip => zip::store_data(buf, location)?.to_vec(),
+        Format::Unknown => superbox(buf, location)?.to_vec(),
+    };
+    if !jumbf::is_c2pa_store(&bytes) {
+        return None;
+    }
+    Some(ExtractedStore { format, bytes })
+}
+
+/// Returns the superbox around the store UUID found by a blind search, which
+/// sits 16 bytes into it
+fn superbox(buf: &[u8], location: Location) -> Option<&[u8]> {
+    let start = location.offset.checked_sub(16)?;
+    let data = buf.get(start..)?;
+    // A box too large to address cannot fit in `buf`
+    let len = usize::try_from(jumbf::read_box_header(data)?.box_len?).ok()?;
+    data.get(..len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassembles_jpeg_segments() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+
+        let store = extract_store(asset).unwrap();
+        let header = jumbf::read_box_header(&store.bytes).unwrap();
+        assert_eq!(store.format, Format::Jpeg);
+        assert_eq!(header.box_len, Some(store.bytes.len() as u64));
+        // More than one APP11 segment's worth
+        assert!(store.bytes.len() > 0xFFFF);
+    }
+
+    #[test]
+    fn extracts_from_single_chunk_containers() {
+        let png = include_bytes!("../../../tools/testing/fixtures/images/c2pa-actions-1.2.png");
+        let avi = include_bytes!("../../../tools/testing/fixtures/images/sample.avi");
+        let pdf = include_bytes!("../../../tools/testing/fixtures/images/sample.pdf");
+
+        assert_eq!(extract_store(png).unwrap().bytes, &png[41..41 + 73892]);
+        assert_eq!(extract_store(avi).unwrap().format, Format::Riff);
+        assert_eq!(extract_store(pdf).unwrap().bytes.len(), 11_567);
+    }
+}
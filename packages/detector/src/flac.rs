@@ -0,0 +1,186 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! FLAC metadata block walker that locates the `APPLICATION` block carrying a
+//! C2PA Manifest Store.
+
+use crate::detection::{Findings, Location};
+use crate::jumbf;
+use crate::plan::ByteRange;
+
+const SIGNATURE: &[u8] = b"fLaC";
+
+const APPLICATION: u8 = 2;
+const LAST_BLOCK: u8 = 0x80;
+
+/// Application identifier of the block holding the store
+const C2PA_APPLICATION: [u8; 4] = *b"c2pa";
+
+/// Block type and 24-bit length
+const BLOCK_HEADER_LEN: usize = 4;
+/// Block header followed by the application identifier
+const APPLICATION_HEADER_LEN: usize = 8;
+
+/// Bytes of the store needed to confirm the manifest store header
+const STORE_PEEK_LEN: usize = 256;
+
+pub fn is_flac(buf: &[u8]) -> bool {
+    buf.starts_with(SIGNATURE)
+}
+
+struct BlockHeader {
+    block_type: u8,
+    last: bool,
+    /// Length of the whole block, header included
+    len: usize,
+}
+
+fn read_block_header(buf: &[u8]) -> Option<BlockHeader> {
+    let header = buf.get(..BLOCK_HEADER_LEN)?;
+    Some(BlockHeader {
+        block_type: header[0] & !LAST_BLOCK,
+        last: header[0] & LAST_BLOCK != 0,
+        len: BLOCK_HEADER_LEN + u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize,
+    })
+}
+
+/// Returns the range of the C2PA application block starting `buf`, located at
+/// absolute offset `offset`, going by its header alone
+pub fn pending_store(buf: &[u8], offset: usize) -> Option<ByteRange> {
+    let header = read_block_header(buf)?;
+    if header.block_type != APPLICATION
+        || buf.get(BLOCK_HEADER_LEN..APPLICATION_HEADER_LEN)? != C2PA_APPLICATION
+    {
+        return None;
+    }
+    Some(ByteRange::new(offset, header.len))
+}
+
+/// Returns the manifest store held by the application block at `location`
+pub fn store_data(buf: &[u8], location: Location) -> Option<&[u8]> {
+    let block = buf.get(location.offset..)?;
+    let block = match location.length {
+        Some(len) => block.get(..len)?,
+        None => block,
+    };
+    if block.get(BLOCK_HEADER_LEN..APPLICATION_HEADER_LEN)? != C2PA_APPLICATION {
+        return None;
+    }
+    Some(&block[APPLICATION_HEADER_LEN..])
+}
+
+/// Resumable walk over the metadata blocks, which precede the audio frames
+#[derive(Debug, Default)]
+pub struct FlacScanner {
+    /// The store location is that of the whole application block
+    pub findings: Findings,
+    /// Absolute offset of the next block
+    pos: usize,
+    finished: bool,
+}
+
+impl FlacScanner {
+    /// Walks the blocks in `buf`, located at absolute offset `base`, and returns
+    /// how many bytes can be dropped, possibly beyond the end of `buf`
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        while !self.finished {
+            // A store block is only settled once it has been seen to its end
+            if self.findings.is_settled() {
+                self.finished = true;
+                break;
+            }
+            let rel = self.pos - base;
+            let available = buf.get(rel..).unwrap_or_default();
+
+            if self.pos == 0 {
+                if available.len() < SIGNATURE.len() {
+                    self.finished = eof;
+                    return rel;
+                }
+                self.pos = SIGNATURE.len();
+                continue;
+            }
+
+            let header = match read_block_header(available) {
+                Some(header) => header,
+                None => {
+                    self.finished = eof;
+                    return rel;
+                }
+            };
+            if header.block_type == APPLICATION {
+                let needed = header.len.min(APPLICATION_HEADER_LEN + STORE_PEEK_LEN);
+                if available.len() < needed && !eof {
+                    return rel;
+                }
+                self.inspect_application(&header, &available[..available.len().min(header.len)]);
+            }
+
+            self.pos += header.len;
+            self.finished = self.finished || header.last;
+        }
+        self.pos - base
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn inspect_application(&mut self, header: &BlockHeader, block: &[u8]) {
+        match block.get(BLOCK_HEADER_LEN..APPLICATION_HEADER_LEN) {
+            Some(id) if id == C2PA_APPLICATION => {}
+            _ => return,
+        }
+        let store = &block[APPLICATION_HEADER_LEN..];
+        if !jumbf::is_c2pa_store(store) {
+            return;
+        }
+        self.findings.add_store(Location {
+            offset: self.pos,
+            length: Some(header.len),
+        });
+        if block.len() == header.len {
+            if let Some(damage) = jumbf::truncation(store) {
+                self.findings.add_damage(self.pos, damage);
+            }
+        }
+        self.finished = self.findings.is_settled();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::extract_store;
+
+    #[test]
+    fn finds_store_in_application_block() {
+        let jpeg = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        let store = extract_store(jpeg).unwrap().bytes;
+        let mut asset = SIGNATURE.to_vec();
+        // STREAMINFO
+        asset.extend_from_slice(&[0, 0, 0, 34]);
+        asset.extend_from_slice(&[0; 34]);
+        // PADDING
+        asset.extend_from_slice(&[1, 0, 0, 8]);
+        asset.extend_from_slice(&[0; 8]);
+        asset.push(LAST_BLOCK | APPLICATION);
+        asset.extend_from_slice(&(4 + store.len() as u32).to_be_bytes()[1..]);
+        asset.extend_from_slice(&C2PA_APPLICATION);
+        asset.extend_from_slice(&store);
+        asset.extend_from_slice(&[0xFF, 0xF8, 0x69, 0x18]);
+
+        let mut scanner = FlacScanner::default();
+        scanner.scan(&asset, 0, true);
+
+        let location = scanner.findings.store().unwrap();
+        assert_eq!(location.offset, 54);
+        assert_eq!(location.length, Some(APPLICATION_HEADER_LEN + store.len()));
+        assert_eq!(store_data(&asset, location), Some(&store[..]));
+        assert!(scanner.is_finished());
+    }
+}
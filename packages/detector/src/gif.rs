@@ -0,0 +1,278 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! GIF block walker that locates the `C2PA_GIF` application extension carrying
+//! a C2PA Manifest Store.
+//!
+//! The store is split across the data sub-blocks of the extension, which hold
+//! at most 255 bytes each.
+
+use crate::detection::{Findings, Location};
+use crate::jumbf;
+use twoway::find_bytes;
+
+const EXTENSION: u8 = 0x21;
+const IMAGE: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+const APPLICATION: u8 = 0xFF;
+
+/// Application identifier and authentication code of the extension holding the store
+const C2PA_APPLICATION: &[u8; 11] = b"C2PA_GIF\x01\0\0";
+const XMP_APPLICATION: &[u8; 11] = b"XMP DataXMP";
+
+/// Start of the "magic trailer" that ends the raw XMP packet in its extension,
+/// making the packet bytes read as valid sub-block sizes
+const XMP_TRAILER: [u8; 4] = [0x01, 0xFF, 0xFE, 0xFD];
+
+/// Signature, version and logical screen descriptor
+const HEADER_LEN: usize = 13;
+/// Introducer, label and the sub-block holding the application identifier
+const APPLICATION_HEADER_LEN: usize = 14;
+/// Separator and image descriptor fields, followed by the LZW code size
+const IMAGE_HEADER_LEN: usize = 10;
+
+/// Extensions larger than this are not inspected
+const MAX_EXTENSION_LEN: usize = 64 * 1024 * 1024;
+
+pub fn is_gif(buf: &[u8]) -> bool {
+    buf.starts_with(b"GIF87a") || buf.starts_with(b"GIF89a")
+}
+
+/// Size of the color table announced by a packed fields byte
+fn color_table_len(packed: u8) -> usize {
+    if packed & 0x80 != 0 {
+        3 << ((packed & 0x07) + 1)
+    } else {
+        0
+    }
+}
+
+/// Walks the data sub-blocks of `buf` from the size byte at `from`, returning their
+/// total length through the terminator, or how far the walk got when the
+/// terminator is not within `buf`
+fn sub_blocks_len(buf: &[u8], from: usize) -> Result<usize, usize> {
+    let mut pos = from;
+    loop {
+        match buf.get(pos) {
+            Some(0) => return Ok(pos + 1),
+            Some(&size) => pos += 1 + size as usize,
+            None => return Err(pos),
+        }
+    }
+}
+
+/// Concatenates the data of the sub-blocks at the start of `buf`, stopping at
+/// the terminator or the end of `buf`
+fn sub_blocks_data(buf: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut pos = 0;
+    while let Some(&size) = buf.get(pos) {
+        if size == 0 {
+            break;
+        }
+        let start = pos + 1;
+        let end = (start + size as usize).min(buf.len());
+        data.extend_from_slice(&buf[start..end]);
+        pos = end;
+    }
+    data
+}
+
+/// Returns the manifest store held by the application extension at `location`
+pub fn store_data(buf: &[u8], location: Location) -> Option<Vec<u8>> {
+    let extension = buf.get(location.offset..)?;
+    let extension = match location.length {
+        Some(len) => extension.get(..len)?,
+        None => extension,
+    };
+    if extension.get(3..APPLICATION_HEADER_LEN)? != C2PA_APPLICATION {
+        return None;
+    }
+    Some(sub_blocks_data(&extension[APPLICATION_HEADER_LEN..]))
+}
+
+/// Resumable walk over the GIF blocks
+#[derive(Debug, Default)]
+pub struct GifScanner {
+    /// The store location is that of the whole application extension, the
+    /// provenance reference comes from an XMP application extension
+    pub findings: Findings,
+    /// Absolute offset of the next block, or sub-block when skipping through one
+    pos: usize,
+    /// Whether `pos` is on the data sub-blocks of a block that is not inspected
+    in_sub_blocks: bool,
+    /// How far the sub-blocks of the incomplete application extension at `pos`
+    /// have been walked, so that they are not walked again as more data arrives
+    walked: usize,
+    finished: bool,
+}
+
+impl GifScanner {
+    /// Walks the blocks in `buf`, located at absolute offset `base`, and returns
+    /// how many bytes can be dropped, possibly beyond the end of `buf`
+    ///
+    /// Application extensions that need to be inspected are left for the next
+    /// call until they are complete, unless `eof` is set.
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        while !self.finished {
+            let rel = self.pos - base;
+            let available = buf.get(rel..).unwrap_or_default();
+
+            if self.in_sub_blocks {
+                match available.first() {
+                    Some(0) => {
+                        self.in_sub_blocks = false;
+                        self.pos += 1;
+                    }
+                    Some(&size) => self.pos += 1 + size as usize,
+                    None => {
+                        self.finished = eof;
+                        return rel;
+                    }
+                }
+                continue;
+            }
+
+            if self.pos == 0 {
+                match available.get(..HEADER_LEN) {
+                    Some(header) => self.pos = HEADER_LEN + color_table_len(header[10]),
+                    None => {
+                        self.finished = eof;
+                        return rel;
+                    }
+                }
+                continue;
+            }
+
+            let needed = match available.first() {
+                Some(&IMAGE) => IMAGE_HEADER_LEN,
+                Some(&EXTENSION) => 2,
+                Some(&TRAILER) => {
+                    self.finished = true;
+                    break;
+                }
+                // Bytes that are not a block
+                Some(_) => {
+                    self.finished = true;
+                    break;
+                }
+                None => 1,
+            };
+            if available.len() < needed {
+                self.finished = eof;
+                return rel;
+            }
+
+            if available[0] == IMAGE {
+                self.pos += IMAGE_HEADER_LEN + color_table_len(available[9]) + 1;
+                self.in_sub_blocks = true;
+                continue;
+            }
+            if available[1] == APPLICATION {
+                if available.len() < APPLICATION_HEADER_LEN && !eof {
+                    return rel;
+                }
+                let application = available.get(3..APPLICATION_HEADER_LEN);
+                if application == Some(&C2PA_APPLICATION[..])
+                    || application == Some(&XMP_APPLICATION[..])
+                {
+                    let data = &available[APPLICATION_HEADER_LEN..];
+                    match sub_blocks_len(data, self.walked) {
+                        Ok(len) if len <= MAX_EXTENSION_LEN => {
+                            self.walked = 0;
+                            self.inspect_application(&available[..APPLICATION_HEADER_LEN + len]);
+                            self.pos += APPLICATION_HEADER_LEN + len;
+                            continue;
+                        }
+                        // Cut off by the end of the file, the rest is skipped below
+                        Err(_) if eof => self.inspect_application(available),
+                        Err(walked) if walked <= MAX_EXTENSION_LEN => {
+                            self.walked = walked;
+                            return rel;
+                        }
+                        _ => {}
+                    }
+                    self.walked = 0;
+                }
+            }
+            self.pos += 2;
+            self.in_sub_blocks = true;
+        }
+        self.pos - base
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Inspects an application extension, which may be cut off by the end of the file
+    fn inspect_application(&mut self, extension: &[u8]) {
+        let data = &extension[APPLICATION_HEADER_LEN..];
+        if extension[3..APPLICATION_HEADER_LEN] == C2PA_APPLICATION[..] {
+            let store = sub_blocks_data(data);
+            if !jumbf::is_c2pa_store(&store) {
+                return;
+            }
+            self.findings.add_store(Location {
+                offset: self.pos,
+                length: Some(extension.len()),
+            });
+            if let Some(damage) = jumbf::truncation(&store) {
+                self.findings.add_damage(self.pos, damage);
+            }
+            self.finished = self.findings.is_settled();
+        } else {
+            let end = find_bytes(data, &XMP_TRAILER).unwrap_or(data.len());
+            self.findings
+                .add_xmp(&data[..end], self.pos + APPLICATION_HEADER_LEN);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_store_in_application_extension() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.gif");
+
+        let mut scanner = GifScanner::default();
+        scanner.scan(asset, 0, true);
+
+        let store = scanner.findings.store().unwrap();
+        assert_eq!(store.offset, 19);
+        let data = store_data(asset, store).unwrap();
+        assert!(jumbf::is_c2pa_store(&data));
+        assert_eq!(
+            jumbf::read_box_header(&data).unwrap().box_len,
+            Some(data.len() as u64)
+        );
+    }
+
+    #[test]
+    fn reads_xmp_extension() {
+        let xmp = br#"<x:xmpmeta><rdf:Description dcterms:provenance="https://example.com/a.c2pa"/></x:xmpmeta>"#;
+        let mut asset = b"GIF89a\x01\0\x01\0\0\0\0".to_vec();
+        asset.extend_from_slice(b"\x21\xFF\x0B");
+        asset.extend_from_slice(XMP_APPLICATION);
+        asset.extend_from_slice(xmp);
+        asset.extend_from_slice(&XMP_TRAILER[..1]);
+        asset.extend((0..=0xFF).rev());
+        asset.extend_from_slice(&[0, TRAILER]);
+
+        let mut scanner = GifScanner::default();
+        scanner.scan(&asset, 0, true);
+
+        let provenance = scanner.findings.provenance().unwrap();
+        assert_eq!(
+            provenance.url.as_deref(),
+            Some("https://example.com/a.c2pa")
+        );
+        assert!(scanner.is_finished());
+    }
+}
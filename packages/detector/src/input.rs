@@ -0,0 +1,112 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Access to asset bytes held by JavaScript.
+//!
+//! Inputs may be an `ArrayBuffer`, a `SharedArrayBuffer` or any view over either.
+//! Bytes are brought into wasm memory with bulk copies, and scans read the asset a
+//! chunk at a time, jumping over data the scanner skips. Only what the scanner
+//! still needs is kept, which for PDF, TIFF and ZIP files, whose structures may
+//! point back to any earlier byte, is everything up to the end of the walk.
+//! Extraction and summaries copy the whole asset.
+
+use crate::detection::{self, DetectionResult, ScanReport};
+use crate::stream::StreamDetector;
+use js_sys::{ArrayBuffer, Object, Reflect, TypeError, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+
+/// Bytes copied into wasm memory at a time when scanning
+const CHUNK_LEN: usize = 1024 * 1024;
+
+/// Returns a `Uint8Array` over the bytes of `buf`, without copying them
+pub fn as_uint8_array(buf: &JsValue) -> Result<Uint8Array, JsValue> {
+    if let Some(array) = buf.dyn_ref::<Uint8Array>() {
+        return Ok(array.clone());
+    }
+    if ArrayBuffer::is_view(buf) {
+        let field = |name: &str| Reflect::get(buf, &JsValue::from_str(name));
+        let offset = field("byteOffset")?.as_f64().unwrap_or_default() as u32;
+        let length = field("byteLength")?.as_f64().unwrap_or_default() as u32;
+        return Ok(Uint8Array::new_with_byte_offset_and_length(
+            &field("buffer")?,
+            offset,
+            length,
+        ));
+    }
+    if buf.is_instance_of::<ArrayBuffer>() || is_shared_array_buffer(buf) {
+        return Ok(Uint8Array::new(buf));
+    }
+    Err(TypeError::new("expected an ArrayBuffer, a SharedArrayBuffer or a view over one").into())
+}
+
+/// `SharedArrayBuffer` is not defined in every context, so it is told by its tag
+/// rather than with `instanceof`
+fn is_shared_array_buffer(buf: &JsValue) -> bool {
+    buf.dyn_ref::<Object>()
+        .is_some_and(|object| object.to_string() == "[object SharedArrayBuffer]")
+}
+
+/// Copies all of `buf` into wasm memory in one go
+pub fn to_vec(buf: &JsValue) -> Result<Vec<u8>, JsValue> {
+    Ok(as_uint8_array(buf)?.to_vec())
+}
+
+/// Copies at most the first `max_len` bytes of `buf` into wasm memory
+pub fn prefix_to_vec(buf: &JsValue, max_len: usize) -> Result<Vec<u8>, JsValue> {
+    let bytes = as_uint8_array(buf)?;
+    let len = (bytes.length() as usize).min(max_len);
+    Ok(bytes.subarray(0, len as u32).to_vec())
+}
+
+/// Same as `StreamDetector::push`, copying `chunk` a piece at a time and not
+/// copying the ranges the detector skips
+pub fn push(detector: &mut StreamDetector, chunk: &JsValue) -> Result<bool, JsValue> {
+    feed(detector, &as_uint8_array(chunk)?);
+    Ok(detector.is_finished())
+}
+
+/// Pushes `bytes` to `detector` a chunk at a time, not copying the ranges it skips
+fn feed(detector: &mut StreamDetector, bytes: &Uint8Array) {
+    let len = bytes.length() as usize;
+    let mut chunk = vec![0; CHUNK_LEN.min(len)];
+    let mut offset = 0;
+    while offset < len {
+        offset += detector.take_skip(len - offset);
+        if offset >= len {
+            break;
+        }
+        let end = len.min(offset + CHUNK_LEN);
+        let chunk = &mut chunk[..end - offset];
+        bytes.subarray(offset as u32, end as u32).copy_to(chunk);
+        offset = end;
+        if detector.push(chunk) {
+            break;
+        }
+    }
+}
+
+/// Same as `detection::detect`, reading `buf` in chunks unless it fits in one
+pub fn detect(buf: &JsValue) -> Result<DetectionResult, JsValue> {
+    let bytes = as_uint8_array(buf)?;
+    if bytes.length() as usize <= CHUNK_LEN {
+        return Ok(detection::detect(&bytes.to_vec()));
+    }
+    let mut detector = StreamDetector::new();
+    feed(&mut detector, &bytes);
+    Ok(detector.finish())
+}
+
+/// Same as `detection::scan_all`, reading `buf` in chunks unless it fits in one
+pub fn scan_all(buf: &JsValue) -> Result<ScanReport, JsValue> {
+    let bytes = as_uint8_array(buf)?;
+    if bytes.length() as usize <= CHUNK_LEN {
+        return Ok(detection::scan_all(&bytes.to_vec()));
+    }
+    let mut detector = StreamDetector::exhaustive();
+    feed(&mut detector, &bytes);
+    Ok(detector.finish_report())
+}
@@ -0,0 +1,289 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! JUMBF box tree parsing for inspecting the structure of a manifest store.
+//!
+//! Parsing never fails: malformed or truncated boxes are kept in the tree with
+//! the issues found, so that broken stores can be debugged.
+
+use crate::jumbf::{self, JUMB, JUMD};
+use serde::Serialize;
+
+/// Trailing 12 bytes shared by the UUIDs of the C2PA and JUMBF content types,
+/// whose first 4 bytes spell out the type
+const CONTENT_TYPE_SUFFIX: [u8; 12] = [
+    0x00, 0x11, 0x00, 0x10, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+/// Content type of embedded file boxes, 40CB0C32-BB8A-489D-A70B-2AD6F47F4369
+const EMBEDDED_FILE_UUID: [u8; 16] = [
+    0x40, 0xCB, 0x0C, 0x32, 0xBB, 0x8A, 0x48, 0x9D, 0xA7, 0x0B, 0x2A, 0xD6, 0xF4, 0x7F, 0x43, 0x69,
+];
+
+const TOGGLE_REQUESTABLE: u8 = 0x01;
+const TOGGLE_LABEL: u8 = 0x02;
+const TOGGLE_ID: u8 = 0x04;
+const TOGGLE_SIGNATURE: u8 = 0x08;
+const TOGGLE_PRIVATE: u8 = 0x10;
+
+/// Nesting beyond this is reported rather than parsed
+const MAX_DEPTH: usize = 32;
+
+/// Problem found while parsing a box
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Issue {
+    /// The box is longer than the data containing it
+    Truncated { declared: u64, available: usize },
+    /// Bytes that do not form a valid box header
+    InvalidHeader { offset: usize, length: usize },
+    /// A superbox does not start with a description box
+    MissingDescription,
+    /// The description box is too short for the fields its toggles announce
+    InvalidDescription,
+    /// Boxes are nested too deeply to be parsed
+    TooDeep,
+}
+
+/// Contents of a `jumd` description box
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Description {
+    /// The C2PA or JUMBF content type (`c2pa`, `c2ma`, `cbor`, …), or the UUID
+    /// in hex when it is not a known one
+    pub content_type: String,
+    pub toggles: u8,
+    pub requestable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    /// Whether a SHA-256 signature of the superbox content is included
+    pub has_signature: bool,
+    /// Whether a private box follows the description fields
+    pub has_private: bool,
+}
+
+/// A box in the tree, with its offset relative to the start of the parsed data
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct JumbfBox {
+    pub offset: usize,
+    #[serde(rename = "type")]
+    pub box_type: String,
+    /// Bytes covered by the box, header included, which is less than declared when truncated
+    pub length: usize,
+    #[serde(skip)]
+    pub header_len: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<Description>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<JumbfBox>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<Issue>,
+}
+
+impl JumbfBox {
+    /// The box content of `buf`, the data the tree was parsed from
+    pub fn content<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+        &buf[self.offset + self.header_len..self.offset + self.length]
+    }
+
+    /// The label from the description box, if this is a labeled superbox
+    pub fn label(&self) -> Option<&str> {
+        self.description.as_ref()?.label.as_deref()
+    }
+
+    /// The C2PA or JUMBF content type from the description box
+    pub fn content_type(&self) -> Option<&str> {
+        Some(&self.description.as_ref()?.content_type)
+    }
+
+    /// The child superbox labeled `label`
+    pub fn child(&self, label: &str) -> Option<&JumbfBox> {
+        self.children
+            .iter()
+            .find(|child| child.label() == Some(label))
+    }
+
+    /// The first content box of type `box_type` in this superbox
+    pub fn content_box(&self, box_type: &str) -> Option<&JumbfBox> {
+        self.children
+            .iter()
+            .skip(1)
+            .find(|child| child.box_type == box_type)
+    }
+}
+
+/// The boxes found at the top level of the parsed data
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct JumbfTree {
+    pub boxes: Vec<JumbfBox>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<Issue>,
+}
+
+/// Parses `buf` as a sequence of JUMBF boxes
+pub fn parse_tree(buf: &[u8]) -> JumbfTree {
+    let mut issues = Vec::new();
+    let boxes = parse_boxes(buf, 0, buf.len(), 0, &mut issues);
+    JumbfTree { boxes, issues }
+}
+
+/// Parses the boxes in `buf[start..end]`, recording problems that do not belong
+/// to a single box in `issues`
+fn parse_boxes(
+    buf: &[u8],
+    start: usize,
+    end: usize,
+    depth: usize,
+    issues: &mut Vec<Issue>,
+) -> Vec<JumbfBox> {
+    let mut boxes = Vec::new();
+    let mut pos = start;
+    while pos < end {
+        let available = &buf[pos..end];
+        let header = match jumbf::read_box_header(available) {
+            Some(header) => header,
+            None => {
+                issues.push(Issue::InvalidHeader {
+                    offset: pos,
+                    length: available.len(),
+                });
+                break;
+            }
+        };
+
+        let mut node = JumbfBox {
+            offset: pos,
+            box_type: String::from_utf8_lossy(&header.box_type).into_owned(),
+            length: available.len(),
+            header_len: header.header_len,
+            description: None,
+            children: Vec::new(),
+            issues: Vec::new(),
+        };
+        match header.box_len {
+            Some(len) if len > available.len() as u64 => node.issues.push(Issue::Truncated {
+                declared: len,
+                available: available.len(),
+            }),
+            Some(len) => node.length = len as usize,
+            // The box extends to the end of its container
+            None => {}
+        }
+
+        if header.box_type == JUMB {
+            if depth >= MAX_DEPTH {
+                node.issues.push(Issue::TooDeep);
+            } else {
+                let mut child_issues = Vec::new();
+                node.children = parse_boxes(
+                    buf,
+                    pos + header.header_len,
+                    pos + node.length,
+                    depth + 1,
+                    &mut child_issues,
+                );
+                node.issues.extend(child_issues);
+                match node.children.first() {
+                    Some(first) if first.box_type.as_bytes() == JUMD => {
+                        match parse_description(first.content(buf)) {
+                            Some(description) => node.description = Some(description),
+                            None => node.issues.push(Issue::InvalidDescription),
+                        }
+                    }
+                    _ => node.issues.push(Issue::MissingDescription),
+                }
+            }
+        }
+
+        pos += node.length;
+        boxes.push(node);
+    }
+    boxes
+}
+
+fn parse_description(content: &[u8]) -> Option<Description> {
+    let uuid = content.get(..16)?;
+    let toggles = *content.get(16)?;
+    let mut rest = &content[17..];
+
+    let label = if toggles & TOGGLE_LABEL != 0 {
+        let end = rest.iter().position(|&b| b == 0)?;
+        let label = String::from_utf8_lossy(&rest[..end]).into_owned();
+        rest = &rest[end + 1..];
+        Some(label)
+    } else {
+        None
+    };
+    let id = if toggles & TOGGLE_ID != 0 {
+        let id = rest.get(..4)?;
+        rest = &rest[4..];
+        Some(u32::from_be_bytes([id[0], id[1], id[2], id[3]]))
+    } else {
+        None
+    };
+    let has_signature = toggles & TOGGLE_SIGNATURE != 0;
+    if has_signature {
+        rest.get(..32)?;
+    }
+
+    Some(Description {
+        content_type: content_type(uuid),
+        toggles,
+        requestable: toggles & TOGGLE_REQUESTABLE != 0,
+        label,
+        id,
+        has_signature,
+        has_private: toggles & TOGGLE_PRIVATE != 0,
+    })
+}
+
+fn content_type(uuid: &[u8]) -> String {
+    if uuid[4..] == CONTENT_TYPE_SUFFIX && uuid[..4].iter().all(u8::is_ascii_graphic) {
+        String::from_utf8_lossy(&uuid[..4]).into_owned()
+    } else if uuid == EMBEDDED_FILE_UUID {
+        "embeddedFile".to_string()
+    } else {
+        uuid.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::extract_store;
+
+    #[test]
+    fn parses_manifest_store() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        let store = extract_store(asset).unwrap().bytes;
+
+        let tree = parse_tree(&store);
+        assert!(tree.issues.is_empty());
+        let root = &tree.boxes[0];
+        assert_eq!(root.length, store.len());
+        assert_eq!(root.label(), Some("c2pa"));
+        // The description box comes first, then the manifests
+        let manifest = &root.children[1];
+        assert_eq!(manifest.content_type(), Some("c2ma"));
+        assert!(manifest.child("c2pa.claim").is_some());
+    }
+
+    #[test]
+    fn marks_truncated_boxes() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        let store = extract_store(asset).unwrap().bytes;
+
+        let tree = parse_tree(&store[..store.len() / 2]);
+        let root = &tree.boxes[0];
+        assert_eq!(root.label(), Some("c2pa"));
+        assert!(matches!(root.issues[0], Issue::Truncated { .. }));
+    }
+}
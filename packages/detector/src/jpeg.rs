@@ -0,0 +1,533 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! JPEG marker walker that locates C2PA Manifest Stores carried in APP11
+//! (JPEG XT / ISO 19566-5) segments.
+
+use crate::detection::{Damage, Findings, Location};
+use crate::jumbf;
+use crate::plan::ByteRange;
+use std::convert::TryFrom;
+
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+const APP1: u8 = 0xE1;
+const APP11: u8 = 0xEB;
+
+/// Common identifier of JPEG XT APP11 segments
+const JPEG_XT_CI: &[u8] = b"JP";
+
+const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+pub fn is_jpeg(buf: &[u8]) -> bool {
+    buf.len() >= 3 && buf[0] == 0xFF && buf[1] == SOI && buf[2] == 0xFF
+}
+
+/// A marker segment read from the JPEG header
+#[derive(Debug, Clone, Copy)]
+pub struct Segment<'a> {
+    pub marker: u8,
+    /// Offset of the `0xFF` byte introducing the marker
+    pub offset: usize,
+    /// Payload length declared by the segment, excluding the length field itself
+    pub declared_len: usize,
+    /// Payload bytes available in the buffer (may be truncated)
+    pub data: &'a [u8],
+}
+
+impl<'a> Segment<'a> {
+    pub fn is_complete(&self) -> bool {
+        self.data.len() == self.declared_len
+    }
+}
+
+/// Iterates over the marker segments preceding the first scan (`SOS`)
+pub struct Segments<'a> {
+    buf: &'a [u8],
+    /// Absolute offset of `buf[0]` within the asset
+    base: usize,
+    pos: usize,
+    at_end: bool,
+}
+
+impl<'a> Segments<'a> {
+    /// Creates an iterator over `buf`, which must start on a marker located at `base`
+    pub fn new(buf: &'a [u8], base: usize) -> Self {
+        Segments {
+            buf,
+            base,
+            pos: 0,
+            at_end: false,
+        }
+    }
+
+    /// Number of bytes of `buf` consumed by the segments returned so far
+    pub fn position(&self) -> usize {
+        self.pos.min(self.buf.len())
+    }
+
+    /// Whether iteration stopped at the end of the header rather than for lack of data
+    pub fn at_end(&self) -> bool {
+        self.at_end
+    }
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = Segment<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let buf = self.buf;
+        loop {
+            if self.at_end {
+                return None;
+            }
+            if *buf.get(self.pos)? != 0xFF {
+                self.at_end = true;
+                return None;
+            }
+            // Markers may be preceded by any number of fill bytes
+            while buf.get(self.pos + 1) == Some(&0xFF) {
+                self.pos += 1;
+            }
+            let offset = self.base + self.pos;
+            let marker = *buf.get(self.pos + 1)?;
+            match marker {
+                EOI | SOS => {
+                    self.at_end = true;
+                    return None;
+                }
+                // Standalone markers carry no length
+                0x01 | 0xD0..=0xD7 | SOI => {
+                    self.pos += 2;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let len =
+                u16::from_be_bytes([*buf.get(self.pos + 2)?, *buf.get(self.pos + 3)?]) as usize;
+            if len < 2 {
+                self.at_end = true;
+                return None;
+            }
+            let start = self.pos + 4;
+            let end = (self.pos + 2 + len).min(buf.len());
+            self.pos += 2 + len;
+
+            return Some(Segment {
+                marker,
+                offset,
+                declared_len: len - 2,
+                data: &buf[start..end],
+            });
+        }
+    }
+}
+
+/// An APP11 segment holding (part of) a JUMBF box
+#[derive(Debug, Clone, Copy)]
+pub struct JumbfSegment<'a> {
+    pub offset: usize,
+    /// Box instance number (`En`), shared by the segments of one box
+    pub box_instance: u16,
+    /// Packet sequence number (`Z`), starting at 1
+    pub sequence: u32,
+    /// The JUMBF box bytes, starting with the (repeated) `LBox`/`TBox` header
+    pub payload: &'a [u8],
+}
+
+impl<'a> JumbfSegment<'a> {
+    pub fn from_segment(segment: &Segment<'a>) -> Option<Self> {
+        let data = segment.data;
+        if segment.marker != APP11 || data.len() < 8 || &data[..2] != JPEG_XT_CI {
+            return None;
+        }
+        Some(JumbfSegment {
+            offset: segment.offset,
+            box_instance: u16::from_be_bytes([data[2], data[3]]),
+            sequence: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            payload: &data[8..],
+        })
+    }
+
+    /// Whether this is the first segment of a C2PA Manifest Store
+    pub fn starts_c2pa_store(&self) -> bool {
+        self.sequence == 1 && jumbf::is_c2pa_store(self.payload)
+    }
+}
+
+/// Returns the ranges of the APP11 segments holding the store that starts with
+/// the segment at `offset`
+///
+/// Segments past the end of `buf` are predicted assuming the writer split the
+/// store evenly, filling every segment but the last as much as the first one.
+pub fn store_ranges(buf: &[u8], offset: usize) -> Vec<ByteRange> {
+    let mut segments = Segments::new(buf.get(offset..).unwrap_or_default(), offset);
+    let (first, declared_len) = match segments.next() {
+        Some(segment) => match JumbfSegment::from_segment(&segment) {
+            Some(jumbf) => (jumbf, segment.declared_len),
+            None => return Vec::new(),
+        },
+        None => return Vec::new(),
+    };
+    let total = 4 + declared_len;
+    let header = match jumbf::read_box_header(first.payload) {
+        Some(header) => header,
+        None => return vec![ByteRange::new(offset, total)],
+    };
+    let box_len = match header.box_len {
+        // A box too large to address runs past the end of the file
+        Some(len) => usize::try_from(len).unwrap_or(usize::MAX),
+        None => return vec![ByteRange::new(offset, total)],
+    };
+
+    let mut ranges = vec![ByteRange::new(offset, total)];
+    // Box bytes carried so far, and the end of the last segment seen
+    let mut carried = declared_len.saturating_sub(8);
+    let mut end = offset + total;
+    for segment in segments.by_ref() {
+        if carried >= box_len {
+            break;
+        }
+        let segment_len = 4 + segment.declared_len;
+        end = segment.offset + segment_len;
+        match JumbfSegment::from_segment(&segment) {
+            Some(jumbf) if jumbf.box_instance == first.box_instance => {
+                // Continuation segments repeat the box header
+                carried += segment.declared_len.saturating_sub(8 + header.header_len);
+                ByteRange::extend(&mut ranges, segment.offset, segment_len);
+            }
+            _ => {}
+        }
+    }
+
+    if carried < box_len && !segments.at_end() {
+        let capacity = declared_len.saturating_sub(8 + header.header_len);
+        let remaining = box_len - carried;
+        if let Some(full) = remaining.checked_div(capacity) {
+            let last = remaining % capacity;
+            let mut length = full.saturating_mul(total);
+            if last > 0 {
+                length = length.saturating_add(4 + 8 + header.header_len + last);
+            }
+            ByteRange::extend(&mut ranges, end, length);
+        }
+    }
+    ranges
+}
+
+/// Reassembles the store starting with the APP11 segment at `offset` from the
+/// payloads of all segments of its box, in sequence order
+pub fn store_data(buf: &[u8], offset: usize) -> Option<Vec<u8>> {
+    let first = Segments::new(buf.get(offset..)?, offset)
+        .next()
+        .and_then(|segment| JumbfSegment::from_segment(&segment))?;
+    let header = jumbf::read_box_header(first.payload)?;
+    // A box too large to address cannot fit in `buf`
+    let box_len = usize::try_from(header.box_len?).ok()?;
+
+    let mut parts: Vec<JumbfSegment> = Segments::new(buf, 0)
+        .filter_map(|segment| JumbfSegment::from_segment(&segment))
+        .filter(|jumbf| jumbf.box_instance == first.box_instance)
+        .collect();
+    parts.sort_by_key(|jumbf| jumbf.sequence);
+    // Repeated packets add nothing, as when scanning
+    parts.dedup_by_key(|jumbf| jumbf.sequence);
+
+    let mut data = Vec::with_capacity(box_len.min(buf.len()));
+    for (i, part) in parts.iter().enumerate() {
+        if part.sequence as usize != i + 1 {
+            return None;
+        }
+        // Continuation segments repeat the box header
+        let payload = if i == 0 {
+            part.payload
+        } else {
+            part.payload.get(header.header_len..)?
+        };
+        data.extend_from_slice(payload);
+    }
+    if data.len() < box_len {
+        return None;
+    }
+    data.truncate(box_len);
+    Some(data)
+}
+
+/// A store whose continuation segments have yet to be seen
+#[derive(Debug, Clone, Copy)]
+struct PendingStore {
+    offset: usize,
+    box_instance: u16,
+    /// Length of the box header repeated by continuation segments
+    header_len: usize,
+    box_len: usize,
+    /// Box bytes carried by the segments seen so far
+    carried: usize,
+    next_sequence: u32,
+}
+
+/// Resumable walk over the JPEG header
+///
+/// Only APP11 segments whose JUMBF superbox and description box form a valid
+/// C2PA store header are reported, so stray UUID bytes in the image data are ignored.
+/// The result is only settled once every segment of the store has been seen, so
+/// that a store missing segments is reported as damaged.
+#[derive(Debug, Default)]
+pub struct JpegScanner {
+    /// The store location is that of the APP11 segment starting it, the provenance
+    /// reference comes from an XMP APP1 segment
+    pub findings: Findings,
+    pending: Option<PendingStore>,
+    finished: bool,
+}
+
+impl JpegScanner {
+    /// Walks the segments in `buf`, which starts on a marker at absolute offset `base`,
+    /// and returns how many bytes were fully processed.
+    ///
+    /// A trailing segment that is only partially available is left for the next call
+    /// unless `eof` is set, in which case it is inspected as-is.
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        let mut segments = Segments::new(buf, base);
+        let mut consumed = 0;
+
+        while let Some(segment) = segments.next() {
+            if !segment.is_complete() && !eof {
+                // The store header can be confirmed before the rest of its segment arrives
+                self.inspect_store(&segment);
+                return consumed;
+            }
+            self.inspect_store(&segment);
+            self.track_store(&segment);
+            if self.finished {
+                return segments.position();
+            }
+            self.inspect_xmp(&segment);
+            consumed = segments.position();
+        }
+
+        if segments.at_end() || eof {
+            self.end_store(segments.at_end());
+            self.finished = true;
+        }
+        segments.position()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn inspect_store(&mut self, segment: &Segment) {
+        if let Some(jumbf) = JumbfSegment::from_segment(segment) {
+            if jumbf.starts_c2pa_store() {
+                self.findings.add_store(Location {
+                    offset: jumbf.offset,
+                    length: None,
+                });
+            }
+        }
+    }
+
+    /// Counts the box bytes carried by a complete segment towards the pending store
+    fn track_store(&mut self, segment: &Segment) {
+        let jumbf = match JumbfSegment::from_segment(segment) {
+            Some(jumbf) => jumbf,
+            None => return,
+        };
+        if jumbf.starts_c2pa_store() {
+            self.end_store(true);
+            let header = jumbf::read_box_header(jumbf.payload);
+            self.pending = header.and_then(|header| {
+                Some(PendingStore {
+                    offset: jumbf.offset,
+                    box_instance: jumbf.box_instance,
+                    header_len: header.header_len,
+                    // A box too large to address runs past the end of the file
+                    box_len: usize::try_from(header.box_len?).unwrap_or(usize::MAX),
+                    carried: 0,
+                    next_sequence: 1,
+                })
+            });
+        }
+
+        let pending = match &mut self.pending {
+            Some(pending) if pending.box_instance == jumbf.box_instance => pending,
+            Some(_) => return,
+            None => {
+                self.finished = self.findings.is_settled();
+                return;
+            }
+        };
+        // Repeated packets add nothing
+        if jumbf.sequence < pending.next_sequence {
+            return;
+        }
+        if jumbf.sequence > pending.next_sequence {
+            self.end_store(true);
+        } else {
+            // Continuation segments repeat the box header
+            let header_len = if jumbf.sequence == 1 {
+                0
+            } else {
+                pending.header_len
+            };
+            pending.carried += jumbf.payload.len().saturating_sub(header_len);
+            pending.next_sequence += 1;
+            if pending.carried >= pending.box_len {
+                self.pending = None;
+            }
+        }
+        self.finished = self.pending.is_none() && self.findings.is_settled();
+    }
+
+    /// Reports the pending store as damaged, as its next segment is missing
+    /// from the header or the file ends before it
+    fn end_store(&mut self, header_ended: bool) {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let damage = if header_ended {
+            Damage::MissingSegment {
+                sequence: pending.next_sequence,
+                expected: pending.box_len,
+                available: pending.carried,
+            }
+        } else {
+            Damage::Truncated {
+                container: "APP11".to_string(),
+                expected: pending.box_len,
+                available: pending.carried,
+            }
+        };
+        self.findings.add_damage(pending.offset, damage);
+    }
+
+    fn inspect_xmp(&mut self, segment: &Segment) {
+        if segment.marker == APP1 && segment.data.starts_with(XMP_NAMESPACE) {
+            self.findings.add_xmp(segment.data, segment.offset + 4);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jumbf::CAI_BLOCK_UUID;
+
+    #[test]
+    fn finds_store_in_app11() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+
+        let mut scanner = JpegScanner::default();
+        scanner.scan(asset, 0, true);
+
+        assert_eq!(scanner.findings.store().map(|store| store.offset), Some(20));
+    }
+
+    #[test]
+    fn ignores_uuid_outside_app11() {
+        let mut asset = include_bytes!("../../../tools/testing/fixtures/images/I.jpg").to_vec();
+        let len = asset.len();
+        asset[len - 100..len - 84].copy_from_slice(&CAI_BLOCK_UUID);
+
+        let mut scanner = JpegScanner::default();
+        scanner.scan(&asset, 0, true);
+
+        assert_eq!(scanner.findings.store(), None);
+    }
+
+    #[test]
+    fn plans_store_too_large_to_address() {
+        // A first APP11 segment whose `jumb` box declares the largest XLBox
+        let mut payload = b"JP\0\x01\0\0\0\x01\0\0\0\x01jumb".to_vec();
+        payload.extend_from_slice(&u64::MAX.to_be_bytes());
+        payload.extend_from_slice(&[0; 32]);
+        let mut asset = vec![0xFF, 0xD8, 0xFF, APP11];
+        asset.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        asset.extend_from_slice(&payload);
+
+        let ranges = store_ranges(&asset, 2);
+        assert_eq!(ranges, vec![ByteRange::new(2, usize::MAX)]);
+    }
+
+    #[test]
+    fn skips_store_too_large_to_address() {
+        let mut payload = b"JP\0\x01\0\0\0\x01\0\0\0\x01jumb".to_vec();
+        payload.extend_from_slice(&u64::MAX.to_be_bytes());
+        payload.extend_from_slice(&[0; 32]);
+        let mut asset = vec![0xFF, 0xD8, 0xFF, APP11];
+        asset.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        asset.extend_from_slice(&payload);
+
+        assert_eq!(store_data(&asset, 2), None);
+    }
+
+    #[test]
+    fn reassembles_store_with_repeated_packet() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        let second = Segments::new(asset, 0)
+            .filter_map(|segment| JumbfSegment::from_segment(&segment))
+            .find(|jumbf| jumbf.sequence == 2)
+            .unwrap();
+        let len = 2 + u16::from_be_bytes([asset[second.offset + 2], asset[second.offset + 3]]);
+        let end = second.offset + len as usize;
+        let mut repeated = asset[..end].to_vec();
+        repeated.extend_from_slice(&asset[second.offset..]);
+
+        let mut scanner = JpegScanner::default();
+        scanner.scan(&repeated, 0, true);
+
+        assert_eq!(scanner.findings.damage(), None);
+        assert_eq!(store_data(&repeated, 20), store_data(asset, 20));
+        assert!(store_data(asset, 20).is_some());
+    }
+
+    #[test]
+    fn reports_missing_segment() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        let second = Segments::new(asset, 0)
+            .filter_map(|segment| JumbfSegment::from_segment(&segment))
+            .find(|jumbf| jumbf.sequence == 2)
+            .unwrap();
+        let len = 2 + u16::from_be_bytes([asset[second.offset + 2], asset[second.offset + 3]]);
+        let mut asset = asset.to_vec();
+        asset.drain(second.offset..second.offset + len as usize);
+
+        let mut scanner = JpegScanner::default();
+        scanner.scan(&asset, 0, true);
+
+        assert!(matches!(
+            scanner.findings.damage(),
+            Some((20, Damage::MissingSegment { sequence: 2, .. }))
+        ));
+    }
+
+    #[test]
+    fn reports_truncated_store() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+
+        let mut scanner = JpegScanner::default();
+        scanner.scan(&asset[..1000], 0, true);
+
+        match scanner.findings.damage() {
+            Some((
+                20,
+                Damage::Truncated {
+                    expected,
+                    available,
+                    ..
+                },
+            )) => {
+                assert!(available < 1000 && expected > 0xFFFF)
+            }
+            other => panic!("unexpected damage {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Minimal JUMBF (ISO/IEC 19566-5) box parsing, just enough to confirm that
+//! a buffer starts with a C2PA Manifest Store superbox.
+
+use crate::detection::Damage;
+use std::convert::TryFrom;
+
+// The C2PA Manifest Store shall have a label of c2pa, a UUID of 0x63327061-0011-0010-8000-00AA00389B71 (c2pa)
+pub const CAI_BLOCK_UUID: [u8; 16] = [
+    0x63, 0x32, 0x70, 0x61, 0x00, 0x11, 0x00, 0x10, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+pub const C2PA_LABEL: &[u8] = b"c2pa";
+
+pub const JUMB: [u8; 4] = *b"jumb";
+pub const JUMD: [u8; 4] = *b"jumd";
+
+/// Toggle bit in a description box signalling that a label is present
+const TOGGLE_LABEL: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoxHeader {
+    pub box_type: [u8; 4],
+    /// Size of the header itself (8, or 16 when `XLBox` is used)
+    pub header_len: usize,
+    /// Total box size including the header, `None` if the box extends to the end of its container
+    pub box_len: Option<u64>,
+}
+
+/// Reads an `LBox`/`TBox`(/`XLBox`) header from the start of `buf`
+pub fn read_box_header(buf: &[u8]) -> Option<BoxHeader> {
+    let lbox = u32::from_be_bytes([*buf.first()?, *buf.get(1)?, *buf.get(2)?, *buf.get(3)?]);
+    let box_type = [*buf.get(4)?, *buf.get(5)?, *buf.get(6)?, *buf.get(7)?];
+
+    let (header_len, box_len) = match lbox {
+        0 => (8, None),
+        1 => {
+            let xl = buf.get(8..16)?;
+            let mut xlbox = [0u8; 8];
+            xlbox.copy_from_slice(xl);
+            let xlbox = u64::from_be_bytes(xlbox);
+            if xlbox < 16 {
+                return None;
+            }
+            (16, Some(xlbox))
+        }
+        2..=7 => return None,
+        len => (8, Some(len as u64)),
+    };
+
+    Some(BoxHeader {
+        box_type,
+        header_len,
+        box_len,
+    })
+}
+
+/// Checks that `buf` begins with a `jumb` superbox whose description box
+/// carries the C2PA UUID and the `c2pa` label.
+///
+/// Only the superbox and description box headers need to be present in `buf`,
+/// so this can be used on the first segment of a store that is split across
+/// several container segments.
+pub fn is_c2pa_store(buf: &[u8]) -> bool {
+    let superbox = match read_box_header(buf) {
+        Some(header) if header.box_type == JUMB => header,
+        _ => return false,
+    };
+    let content = &buf[superbox.header_len..];
+
+    let desc = match read_box_header(content) {
+        Some(header) if header.box_type == JUMD => header,
+        _ => return false,
+    };
+    // The description box must fit inside its superbox
+    if let (Some(super_len), Some(desc_len)) = (superbox.box_len, desc.box_len) {
+        match desc_len.checked_add(superbox.header_len as u64) {
+            Some(end) if end <= super_len => {}
+            _ => return false,
+        }
+    }
+
+    let desc_content = &content[desc.header_len..];
+    let desc_content = match desc.box_len {
+        Some(len) => {
+            let len = usize::try_from(len)
+                .unwrap_or(usize::MAX)
+                .saturating_sub(desc.header_len);
+            match desc_content.get(..len) {
+                Some(content) => content,
+                None => return false,
+            }
+        }
+        None => desc_content,
+    };
+
+    if desc_content.len() < 17 || desc_content[..16] != CAI_BLOCK_UUID {
+        return false;
+    }
+    if desc_content[16] & TOGGLE_LABEL == 0 {
+        return false;
+    }
+
+    let label = &desc_content[17..];
+    match label.iter().position(|&b| b == 0) {
+        Some(end) => &label[..end] == C2PA_LABEL,
+        None => false,
+    }
+}
+
+/// Returns the damage of a store whose superbox declares more bytes than the
+/// complete container `data` holds
+pub fn truncation(data: &[u8]) -> Option<Damage> {
+    let expected = read_box_header(data)?.box_len?;
+    if expected <= data.len() as u64 {
+        return None;
+    }
+    Some(Damage::Truncated {
+        container: String::from_utf8_lossy(&JUMB).into_owned(),
+        expected: usize::try_from(expected).unwrap_or(usize::MAX),
+        available: data.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_description_box_overflowing_superbox() {
+        let mut store = b"\0\0\0\x01jumb".to_vec();
+        store.extend_from_slice(&u64::MAX.to_be_bytes());
+        store.extend_from_slice(b"\0\0\0\x01jumd");
+        store.extend_from_slice(&u64::MAX.to_be_bytes());
+        store.extend_from_slice(&CAI_BLOCK_UUID);
+        store.extend_from_slice(b"\x03c2pa\0");
+
+        assert!(!is_c2pa_store(&store));
+    }
+
+    #[test]
+    fn rejects_description_box_too_large_to_address() {
+        // The superbox runs to the end of the file, so cannot bound its description
+        let mut store = b"\0\0\0\0jumb\0\0\0\x01jumd".to_vec();
+        store.extend_from_slice(&((1u64 << 32) + 24).to_be_bytes());
+        store.extend_from_slice(&CAI_BLOCK_UUID);
+        store.extend_from_slice(b"\x03c2pa\0");
+
+        assert!(!is_c2pa_store(&store));
+    }
+
+    #[test]
+    fn reports_truncation_of_box_too_large_to_address() {
+        let mut store = b"\0\0\0\x01jumb".to_vec();
+        store.extend_from_slice(&u64::MAX.to_be_bytes());
+
+        assert_eq!(
+            truncation(&store),
+            Some(Damage::Truncated {
+                container: "jumb".to_string(),
+                expected: usize::MAX,
+                available: 16,
+            })
+        );
+    }
+}
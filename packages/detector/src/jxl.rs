@@ -0,0 +1,245 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! JPEG XL (ISO/IEC 18181-2) box walker that locates the top-level `jumb` box
+//! holding a C2PA Manifest Store.
+//!
+//! Only the container form can carry metadata; a naked codestream has nowhere
+//! to put a store. Brotli-compressed (`brob`) boxes are not inspected.
+
+use crate::detection::{Findings, Location};
+use crate::jumbf::{self, JUMB};
+use crate::plan::ByteRange;
+use std::convert::TryFrom;
+
+/// The `JXL ` signature box starting the container form
+const SIGNATURE: [u8; 12] = [
+    0x00, 0x00, 0x00, 0x0C, b'J', b'X', b'L', b' ', 0x0D, 0x0A, 0x87, 0x0A,
+];
+/// Start of a naked codestream
+const CODESTREAM: [u8; 2] = [0xFF, 0x0A];
+
+const XML: [u8; 4] = *b"xml ";
+
+/// Bytes of a `jumb` box needed to confirm the manifest store header
+const STORE_PEEK_LEN: usize = 256;
+
+/// XMP boxes larger than this are not inspected
+const MAX_XMP_LEN: usize = 1024 * 1024;
+
+/// Largest possible box header: `LBox`, `TBox` and `XLBox`
+const MAX_HEADER_LEN: usize = 16;
+
+/// Whether `buf` starts like either form, given its first 8 bytes
+pub fn is_jxl(buf: &[u8]) -> bool {
+    is_codestream(buf) || (buf.len() >= 8 && buf[..8] == SIGNATURE[..8])
+}
+
+pub fn is_codestream(buf: &[u8]) -> bool {
+    buf.starts_with(&CODESTREAM)
+}
+
+/// Returns the range of the C2PA `jumb` box starting `buf`, located at absolute
+/// offset `offset`, going by its header alone
+///
+/// A box extending to the end of the file is given a length of `usize::MAX`.
+pub fn pending_store(buf: &[u8], offset: usize) -> Option<ByteRange> {
+    let header = jumbf::read_box_header(buf)?;
+    if header.box_type != JUMB || !jumbf::is_c2pa_store(buf) {
+        return None;
+    }
+    let length = header
+        .box_len
+        .map_or(usize::MAX, |len| usize::try_from(len).unwrap_or(usize::MAX));
+    Some(ByteRange::new(offset, length))
+}
+
+/// Returns the manifest store at `location`, which is the `jumb` box itself
+pub fn store_data(buf: &[u8], location: Location) -> Option<&[u8]> {
+    let data = buf.get(location.offset..)?;
+    match location.length {
+        Some(len) => data.get(..len),
+        None => Some(data),
+    }
+}
+
+/// Resumable walk over the top-level boxes of the container
+#[derive(Debug, Default)]
+pub struct JxlScanner {
+    /// The store location is that of the whole `jumb` box, the provenance
+    /// reference comes from the `xml ` box
+    pub findings: Findings,
+    /// Absolute offset of the next box
+    pos: usize,
+    /// The asset is a naked codestream
+    codestream: bool,
+    finished: bool,
+}
+
+impl JxlScanner {
+    /// Walks the boxes in `buf`, located at absolute offset `base`, and returns
+    /// how many bytes can be dropped, possibly beyond the end of `buf`
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        if self.pos == 0 && is_codestream(buf) {
+            self.codestream = true;
+            self.finished = true;
+        }
+        while !self.finished {
+            // A store box is only settled once it has been seen to its end
+            if self.findings.is_settled() {
+                self.finished = true;
+                break;
+            }
+            let rel = self.pos - base;
+            let available = buf.get(rel..).unwrap_or_default();
+
+            let header = match jumbf::read_box_header(available) {
+                Some(header) => header,
+                None => {
+                    // Either the header is incomplete or its size is invalid
+                    self.finished = eof || available.len() >= MAX_HEADER_LEN;
+                    return rel;
+                }
+            };
+            let box_len = match header.box_len {
+                // A box too large to address runs past the end of the file
+                Some(len) if len >= header.header_len as u64 => usize::try_from(len).ok(),
+                Some(_) => {
+                    self.finished = true;
+                    return rel;
+                }
+                None => None,
+            };
+
+            let to_end = box_len.unwrap_or(usize::MAX);
+            let needed = match header.box_type {
+                JUMB => to_end.min(STORE_PEEK_LEN),
+                XML if to_end <= MAX_XMP_LEN => to_end,
+                _ => 0,
+            };
+            if available.len() < needed && !eof {
+                return rel;
+            }
+            if needed > 0 {
+                let data = &available[..available.len().min(to_end)];
+                self.inspect(header.box_type, header.header_len, box_len, data);
+            }
+
+            match box_len {
+                Some(len) => match self.pos.checked_add(len) {
+                    Some(next) => self.pos = next,
+                    None => self.finished = true,
+                },
+                // The box extends to the end of the file
+                None => self.finished = true,
+            }
+        }
+        self.pos - base
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Whether the asset turned out to be a naked codestream, which cannot hold a store
+    pub fn is_codestream(&self) -> bool {
+        self.codestream
+    }
+
+    fn inspect(
+        &mut self,
+        box_type: [u8; 4],
+        header_len: usize,
+        box_len: Option<usize>,
+        data: &[u8],
+    ) {
+        if box_type == JUMB {
+            if jumbf::is_c2pa_store(data) {
+                self.findings.add_store(Location {
+                    offset: self.pos,
+                    length: box_len,
+                });
+                self.finished = self.findings.is_settled();
+            }
+        } else {
+            self.findings
+                .add_xmp(&data[header_len.min(data.len())..], self.pos + header_len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::{detect, DetectionResult, Format};
+    use crate::extract::extract_store;
+
+    fn container(store: &[u8]) -> Vec<u8> {
+        let mut asset = SIGNATURE.to_vec();
+        asset.extend_from_slice(b"\0\0\0\x14ftypjxl \0\0\0\0jxl ");
+        asset.extend_from_slice(b"\0\0\0\x0Cjxlc\xFF\x0A\0\0");
+        asset.extend_from_slice(store);
+        asset
+    }
+
+    #[test]
+    fn finds_store_in_jumb_box() {
+        let jpeg = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        let store = extract_store(jpeg).unwrap().bytes;
+        let asset = container(&store);
+
+        let mut scanner = JxlScanner::default();
+        scanner.scan(&asset, 0, true);
+
+        let location = scanner.findings.store().unwrap();
+        assert_eq!(location.offset, 44);
+        assert_eq!(store_data(&asset, location), Some(&store[..]));
+    }
+
+    #[test]
+    fn reports_store_with_xlbox() {
+        let jpeg = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        let store = extract_store(jpeg).unwrap().bytes;
+        // Declare a 64-bit XLBox beyond what a 32-bit target can address
+        let xlbox = (1u64 << 32) + store.len() as u64 + 8;
+        let mut jumb = vec![0, 0, 0, 1];
+        jumb.extend_from_slice(&JUMB);
+        jumb.extend_from_slice(&xlbox.to_be_bytes());
+        jumb.extend_from_slice(&store[8..]);
+        let asset = container(&jumb);
+
+        let mut scanner = JxlScanner::default();
+        scanner.scan(&asset, 0, true);
+
+        assert_eq!(
+            scanner.findings.store(),
+            Some(Location {
+                offset: 44,
+                length: usize::try_from(xlbox).ok(),
+            })
+        );
+        assert_eq!(
+            pending_store(&asset[44..], 44).map(|range| range.length),
+            Some(usize::try_from(xlbox).unwrap_or(usize::MAX))
+        );
+    }
+
+    #[test]
+    fn stops_at_naked_codestream() {
+        let mut scanner = JxlScanner::default();
+        scanner.scan(b"\xFF\x0A\xFA\x7F\x01\x90\x08", 0, false);
+
+        assert!(scanner.is_finished());
+        assert!(scanner.is_codestream());
+        assert_eq!(
+            detect(b"\xFF\x0A\xFA\x7F\x01\x90\x08"),
+            DetectionResult::NoContainer {
+                format: Format::Jxl
+            }
+        );
+    }
+}
@@ -5,34 +5,54 @@
 // accordance with the terms of the Adobe license agreement accompanying
 // it.
 
-use std::panic;
-use twoway::find_bytes;
-use wasm_bindgen::prelude::*;
+//! Detection of C2PA manifest stores and provenance references in assets.
+//!
+//! The scanning logic is independent of the target. The JavaScript bindings are
+//! behind the default `wasm` feature, and the `c2pa-detect` command-line scanner
+//! behind the `cli` feature.
 
-#[wasm_bindgen(start)]
-pub fn main() {
-    panic::set_hook(Box::new(console_error_panic_hook::hook));
-}
+#[cfg(all(test, feature = "wasm", target_arch = "wasm32"))]
+mod bench;
+mod bmff;
+mod cbor;
+mod crc32;
+mod der;
+mod detection;
+mod extract;
+mod flac;
+mod gif;
+#[cfg(feature = "wasm")]
+mod input;
+mod inspect;
+mod jpeg;
+mod jumbf;
+mod jxl;
+mod links;
+mod mp3;
+mod pdf;
+mod plan;
+mod png;
+mod riff;
+mod sniff;
+mod stream;
+mod summary;
+mod tiff;
+mod url;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod xml;
+mod xmp;
+mod zip;
 
-// The C2PA Manifest Store shall have a label of c2pa, a UUID of 0x63327061-0011-0010-8000-00AA00389B71 (c2pa)
-const CAI_BLOCK_UUID: [u8; 16] = [
-    0x63, 0x32, 0x70, 0x61, 0x00, 0x11, 0x00, 0x10, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
-];
-
-const DCTERMS_PROVENANCE: [u8; 18] = [
-    0x64, 0x63, 0x74, 0x65, 0x72, 0x6D, 0x73, 0x3A, 0x70, 0x72, 0x6F, 0x76, 0x65, 0x6E, 0x61, 0x6E,
-    0x63, 0x65,
-];
-
-#[wasm_bindgen]
-pub fn scan_array_buffer(buf: JsValue) -> Result<usize, JsValue> {
-    let scan_bytes: serde_bytes::ByteBuf = serde_wasm_bindgen::from_value(buf)?;
-
-    if let Some(pos) = find_bytes(&scan_bytes, &CAI_BLOCK_UUID) {
-        Ok(pos)
-    } else if let Some(pos) = find_bytes(&scan_bytes, &DCTERMS_PROVENANCE) {
-        Ok(pos)
-    } else {
-        Err(JsValue::from_str("NOT_FOUND"))
-    }
-}
+pub use detection::{
+    detect, detect_head, scan_all, Damage, DetectionResult, Format, Occurrence, ScanReport,
+};
+pub use extract::{extract_store, ExtractedStore};
+pub use inspect::{
+    parse_tree, Description as JumbfDescription, Issue as JumbfIssue, JumbfBox, JumbfTree,
+};
+pub use links::{html_manifest_links, link_header_manifest_links};
+pub use plan::{plan_ranges, ByteRange, RangePlan};
+pub use sniff::sniff_format;
+pub use stream::{detect_reader, scan_all_reader, StreamDetector};
+pub use summary::{summarize, summarize_store, ManifestSummary};
@@ -0,0 +1,401 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Discovery of manifest stores referenced from web content, by HTML `<link>`
+//! elements and HTTP `Link` headers with the `c2pa-manifest` relation.
+
+use crate::url;
+
+/// Link relation pointing to the manifest store of a document or asset
+const MANIFEST_REL: &str = "c2pa-manifest";
+
+/// Elements whose content is text, which may contain markup-like strings
+const RAW_TEXT_ELEMENTS: [&str; 8] = [
+    "iframe", "noembed", "noframes", "script", "style", "textarea", "title", "xmp",
+];
+
+fn has_manifest_rel(rel: &str) -> bool {
+    rel.split_ascii_whitespace()
+        .any(|relation| relation.eq_ignore_ascii_case(MANIFEST_REL))
+}
+
+/// Resolves `hrefs` against `base_url`, dropping those that cannot be resolved
+/// and repeated URLs
+fn resolve_all<'a>(base_url: &str, hrefs: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for url in hrefs.filter_map(|href| url::resolve(base_url, href)) {
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    urls
+}
+
+/// A start or end tag
+struct Tag<'a> {
+    name: &'a str,
+    end: bool,
+    attributes: Vec<(&'a str, String)>,
+}
+
+impl Tag<'_> {
+    fn is(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Tokenizer yielding the tags of an HTML document, skipping comments, doctypes
+/// and the content of raw text elements such as `<script>`
+struct Tags<'a> {
+    html: &'a str,
+    pos: usize,
+}
+
+impl<'a> Tags<'a> {
+    fn new(html: &'a str) -> Self {
+        Tags { html, pos: 0 }
+    }
+
+    /// Moves past the next occurrence of `delimiter`, or to the end
+    fn skip_past(&mut self, delimiter: &str) {
+        self.pos = match self.html[self.pos..].find(delimiter) {
+            Some(pos) => self.pos + pos + delimiter.len(),
+            None => self.html.len(),
+        };
+    }
+
+    /// Moves to the end tag closing the raw text element `name`
+    fn skip_raw_text(&mut self, name: &str) {
+        while let Some(pos) = self.html[self.pos..].find("</") {
+            self.pos += pos;
+            let rest = &self.html.as_bytes()[self.pos + 2..];
+            if rest.len() >= name.len() && rest[..name.len()].eq_ignore_ascii_case(name.as_bytes())
+            {
+                return;
+            }
+            self.pos += 2;
+        }
+        self.pos = self.html.len();
+    }
+
+    /// Reads up to the next whitespace, `/`, `>` or, if `at_equals`, `=`
+    fn word(&mut self, at_equals: bool) -> &'a str {
+        let rest = &self.html[self.pos..];
+        let len = rest
+            .find(|c: char| {
+                c.is_ascii_whitespace() || c == '/' || c == '>' || (at_equals && c == '=')
+            })
+            .unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.html[self.pos..];
+        self.pos += rest.len()
+            - rest
+                .trim_start_matches(|c: char| c.is_ascii_whitespace())
+                .len();
+    }
+
+    fn attribute_value(&mut self) -> String {
+        let rest = &self.html[self.pos..];
+        let raw = match rest.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                let len = rest[1..].find(quote).unwrap_or(rest.len() - 1);
+                self.pos += (len + 2).min(rest.len());
+                &rest[1..1 + len]
+            }
+            _ => {
+                let len = rest
+                    .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                &rest[..len]
+            }
+        };
+        decode_references(raw)
+    }
+
+    /// Reads the tag whose name starts at the current position, up to its `>`
+    fn tag(&mut self, end: bool) -> Tag<'a> {
+        let name = self.word(false);
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let rest = &self.html[self.pos..];
+            if rest.is_empty() {
+                break;
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            if rest.starts_with('/') {
+                self.pos += 1;
+                continue;
+            }
+            let attribute = match self.word(true) {
+                // An attribute name may start with `=`
+                "" => {
+                    self.pos += 1;
+                    &rest[..1]
+                }
+                attribute => attribute,
+            };
+            self.skip_whitespace();
+            let value = if self.html[self.pos..].starts_with('=') {
+                self.pos += 1;
+                self.skip_whitespace();
+                self.attribute_value()
+            } else {
+                String::new()
+            };
+            attributes.push((attribute, value));
+        }
+        Tag {
+            name,
+            end,
+            attributes,
+        }
+    }
+}
+
+impl<'a> Iterator for Tags<'a> {
+    type Item = Tag<'a>;
+
+    fn next(&mut self) -> Option<Tag<'a>> {
+        loop {
+            self.pos += self.html[self.pos..].find('<')?;
+            let rest = &self.html[self.pos..];
+            if rest.starts_with("<!--") {
+                self.pos += 4;
+                self.skip_past("-->");
+            } else if rest.starts_with("<!") || rest.starts_with("<?") {
+                self.skip_past(">");
+            } else if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+                self.pos += 1;
+                let tag = self.tag(false);
+                if let Some(element) = RAW_TEXT_ELEMENTS.iter().find(|&&element| tag.is(element)) {
+                    self.skip_raw_text(element);
+                }
+                return Some(tag);
+            } else if rest[1..].starts_with('/')
+                && rest[2..].starts_with(|c: char| c.is_ascii_alphabetic())
+            {
+                self.pos += 2;
+                return Some(self.tag(true));
+            } else {
+                self.pos += 1;
+            }
+        }
+    }
+}
+
+/// Decodes the character references in an attribute value
+///
+/// Only numeric references and the named ones found in URLs are known, others
+/// are left as written.
+fn decode_references(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(pos) = rest.find('&') {
+        decoded.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let end = rest.find(';').filter(|&end| end <= 10);
+        let c = end.and_then(|end| match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            name => match name.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16)
+                    .ok()
+                    .and_then(char::from_u32),
+                Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                None => None,
+            },
+        });
+        match (c, end) {
+            (Some(c), Some(end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Returns the manifest store URLs of the `<link rel="c2pa-manifest">` elements
+/// in the head of an HTML document served from `base_url`
+///
+/// Relative URLs are resolved against the document's `<base>` element, if any,
+/// and `base_url`, and dropped when `base_url` is not an absolute URL. The body
+/// of the document is not read.
+pub fn html_manifest_links(html: &str, base_url: &str) -> Vec<String> {
+    let mut base_href = None;
+    let mut hrefs = Vec::new();
+    for tag in Tags::new(html) {
+        if tag.is("body") || (tag.end && tag.is("head")) {
+            break;
+        }
+        if tag.end {
+            continue;
+        }
+        if tag.is("base") && base_href.is_none() {
+            base_href = tag.attribute("href").map(String::from);
+        } else if tag.is("link") && tag.attribute("rel").is_some_and(has_manifest_rel) {
+            if let Some(href) = tag.attribute("href") {
+                hrefs.push(href.to_string());
+            }
+        }
+    }
+
+    let base_url = base_href
+        .and_then(|href| url::resolve(base_url, &href))
+        .unwrap_or_else(|| base_url.to_string());
+    resolve_all(&base_url, hrefs.iter().map(String::as_str))
+}
+
+/// Reads a quoted string, unescaping it, or a token from the start of `s`,
+/// returning it with what follows
+fn header_value(s: &str) -> (String, &str) {
+    let quoted = match s.strip_prefix('"') {
+        Some(quoted) => quoted,
+        None => {
+            let len = s
+                .find(|c: char| c == ';' || c == ',' || c.is_ascii_whitespace())
+                .unwrap_or(s.len());
+            return (s[..len].to_string(), &s[len..]);
+        }
+    };
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '"' => return (value, &quoted[pos + 1..]),
+            '\\' => value.extend(chars.next().map(|(_, c)| c)),
+            c => value.push(c),
+        }
+    }
+    (value, "")
+}
+
+/// Returns the manifest store URLs of the links with the `c2pa-manifest` relation
+/// in the value of an HTTP `Link` header (RFC 8288), resolved against `base_url`,
+/// the URL of the response
+///
+/// Links that cannot be resolved because `base_url` is not an absolute URL are dropped.
+pub fn link_header_manifest_links(value: &str, base_url: &str) -> Vec<String> {
+    let mut hrefs = Vec::new();
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+        let target = match rest
+            .strip_prefix('<')
+            .and_then(|s| s.find('>').map(|end| (s, end)))
+        {
+            Some((s, end)) => {
+                rest = &s[end + 1..];
+                &s[..end]
+            }
+            None => break,
+        };
+
+        // Only the first `rel` parameter counts
+        let mut rel = None;
+        while let Some(params) = rest.trim_start().strip_prefix(';') {
+            let params = params.trim_start();
+            let len = params
+                .find(|c: char| c == '=' || c == ';' || c == ',' || c.is_ascii_whitespace())
+                .unwrap_or(params.len());
+            let name = &params[..len];
+            rest = params[len..].trim_start();
+            let value = match rest.strip_prefix('=') {
+                Some(value) => {
+                    let (value, after) = header_value(value.trim_start());
+                    rest = after;
+                    value
+                }
+                None => String::new(),
+            };
+            if name.eq_ignore_ascii_case("rel") && rel.is_none() {
+                rel = Some(value);
+            }
+        }
+        if rel.as_deref().is_some_and(has_manifest_rel) {
+            hrefs.push(target);
+        }
+
+        // Skip whatever could not be read up to the next link
+        rest = rest.find(',').map_or("", |pos| &rest[pos..]);
+    }
+    resolve_all(base_url, hrefs.into_iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_links_in_head() {
+        let html = r#"<!DOCTYPE html>
+<html><HEAD>
+  <!-- <link rel="c2pa-manifest" href="commented.c2pa"> -->
+  <base href="/assets/">
+  <script>document.write('<link rel="c2pa-manifest" href="scripted.c2pa">')</script>
+  <link rel=stylesheet href=style.css>
+  <LINK REL="alternate C2PA-Manifest" HREF="page.c2pa?v=1&amp;lang=en">
+  <link href='https://cdn.example/page.c2pa' rel='c2pa-manifest'/>
+  <link rel="c2pa-manifest" href="page.c2pa?v=1&amp;lang=en">
+</head>
+<body><link rel="c2pa-manifest" href="body.c2pa"></body></html>"#;
+
+        assert_eq!(
+            html_manifest_links(html, "https://example.com/articles/story.html"),
+            vec![
+                "https://example.com/assets/page.c2pa?v=1&lang=en",
+                "https://cdn.example/page.c2pa",
+            ]
+        );
+        assert_eq!(
+            html_manifest_links(html, "story.html"),
+            vec!["https://cdn.example/page.c2pa"]
+        );
+    }
+
+    #[test]
+    fn finds_links_in_header() {
+        let value = r#"</style.css>; rel=preload; as=style, <../m/photo.c2pa>; title="a, b; c"; rel="c2pa-manifest alternate", <https://cdn.example/photo.c2pa>;rel=C2PA-MANIFEST;rel=other, <ignored.c2pa>; rel="other"; rel=c2pa-manifest"#;
+
+        assert_eq!(
+            link_header_manifest_links(value, "https://example.com/images/photo.jpg"),
+            vec![
+                "https://example.com/m/photo.c2pa",
+                "https://cdn.example/photo.c2pa",
+            ]
+        );
+        assert!(link_header_manifest_links(
+            "not a link; rel=c2pa-manifest",
+            "https://example.com/"
+        )
+        .is_empty());
+    }
+}
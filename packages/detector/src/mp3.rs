@@ -0,0 +1,418 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! ID3v2 tag parser that locates the `GEOB` frame carrying a C2PA Manifest
+//! Store at the start of an MP3 file.
+//!
+//! Versions 2.3 and 2.4 are supported, with or without unsynchronisation and
+//! extended headers. Compressed and encrypted frames are not inspected.
+
+use crate::detection::{Findings, Location};
+use crate::jumbf;
+use std::borrow::Cow;
+
+const ID3: &[u8] = b"ID3";
+const GEOB: [u8; 4] = *b"GEOB";
+const PRIV: [u8; 4] = *b"PRIV";
+
+/// MIME type of the encapsulated object holding the store
+const C2PA_MIME: &[u8] = b"application/x-c2pa-manifest-store";
+/// Owner identifier of the private frame holding an XMP packet
+const XMP_OWNER: &[u8] = b"XMP\0";
+
+/// Tag and frame headers have the same length
+const HEADER_LEN: usize = 10;
+
+const FLAG_UNSYNC: u8 = 0x80;
+const FLAG_EXTENDED: u8 = 0x40;
+/// Version 2.4 only
+const FLAG_FOOTER: u8 = 0x10;
+
+/// Frame format flags of version 2.3
+const V3_COMPRESSED: u8 = 0x80;
+const V3_ENCRYPTED: u8 = 0x40;
+const V3_GROUPING: u8 = 0x20;
+/// Frame format flags of version 2.4
+const V4_GROUPING: u8 = 0x40;
+const V4_COMPRESSED: u8 = 0x08;
+const V4_ENCRYPTED: u8 = 0x04;
+const V4_UNSYNC: u8 = 0x02;
+const V4_DATA_LENGTH: u8 = 0x01;
+
+/// Tags larger than this are inspected as far as they have arrived
+const MAX_TAG_LEN: usize = 64 * 1024 * 1024;
+
+/// An ID3v2 tag or an MPEG audio layer III frame header
+pub fn is_mp3(buf: &[u8]) -> bool {
+    buf.starts_with(ID3) || (buf.len() >= 2 && buf[0] == 0xFF && buf[1] & 0xE6 == 0xE2)
+}
+
+/// Decodes a 28-bit integer stored 7 bits per byte
+fn syncsafe(buf: &[u8]) -> usize {
+    buf.iter().fold(0, |n, &b| n << 7 | (b & 0x7F) as usize)
+}
+
+fn read_u32(buf: &[u8]) -> usize {
+    u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize
+}
+
+/// Reverses unsynchronisation, dropping the zero inserted after each `0xFF`
+fn resync(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut prev = 0;
+    for &b in data {
+        if !(prev == 0xFF && b == 0) {
+            out.push(b);
+        }
+        prev = b;
+    }
+    out
+}
+
+/// Offsets into the resynchronised form of `raw` at which `resync` dropped a zero
+fn dropped_zeros(raw: &[u8]) -> Vec<usize> {
+    let mut dropped = Vec::new();
+    let mut prev = 0;
+    for (pos, &b) in raw.iter().enumerate() {
+        if prev == 0xFF && b == 0 {
+            dropped.push(pos - dropped.len());
+        }
+        prev = b;
+    }
+    dropped
+}
+
+/// Maps an offset into the resynchronised form of `raw` back to `raw`, given the
+/// zeros dropped from it
+fn raw_offset(raw: &[u8], dropped: &[usize], index: usize) -> usize {
+    if index >= raw.len() - dropped.len() {
+        return raw.len();
+    }
+    index + dropped.partition_point(|&at| at <= index)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tag {
+    major_version: u8,
+    flags: u8,
+    /// Length of the whole tag, header and footer included
+    len: usize,
+}
+
+fn read_tag(buf: &[u8]) -> Option<Tag> {
+    if !buf.starts_with(ID3) {
+        return None;
+    }
+    let header = buf.get(..HEADER_LEN)?;
+    let flags = header[5];
+    let footer = if header[3] == 4 && flags & FLAG_FOOTER != 0 {
+        HEADER_LEN
+    } else {
+        0
+    };
+    Some(Tag {
+        major_version: header[3],
+        flags,
+        len: HEADER_LEN + syncsafe(&header[6..10]) + footer,
+    })
+}
+
+/// A frame of the tag
+#[derive(Debug)]
+struct Frame {
+    id: [u8; 4],
+    /// Offset and length of the whole frame in the file
+    offset: usize,
+    length: usize,
+    /// Frame data with unsynchronisation and extra header fields removed, which
+    /// is cut short when the tag is truncated
+    content: Option<Vec<u8>>,
+    complete: bool,
+}
+
+/// Reads the frames of `tag` from `data`, the start of the file
+fn read_frames(tag: &Tag, data: &[u8]) -> Vec<Frame> {
+    if tag.major_version != 3 && tag.major_version != 4 {
+        return Vec::new();
+    }
+    let raw = &data[HEADER_LEN.min(data.len())..data.len().min(tag.len)];
+    // Version 2.4 unsynchronises frames individually
+    let unsynced = tag.major_version == 3 && tag.flags & FLAG_UNSYNC != 0;
+    let (body, dropped): (Cow<[u8]>, _) = if unsynced {
+        (Cow::Owned(resync(raw)), dropped_zeros(raw))
+    } else {
+        (Cow::Borrowed(raw), Vec::new())
+    };
+    let to_file = |index: usize| {
+        HEADER_LEN.saturating_add(if unsynced {
+            raw_offset(raw, &dropped, index)
+        } else {
+            index
+        })
+    };
+
+    let mut pos = 0;
+    if tag.flags & FLAG_EXTENDED != 0 {
+        pos = match body.get(..4) {
+            Some(size) if tag.major_version == 3 => match read_u32(size).checked_add(4) {
+                Some(end) => end,
+                None => return Vec::new(),
+            },
+            Some(size) => syncsafe(size),
+            None => return Vec::new(),
+        };
+    }
+
+    let mut frames = Vec::new();
+    while let Some(header) = pos
+        .checked_add(HEADER_LEN)
+        .and_then(|end| body.get(pos..end))
+    {
+        // Padding follows the last frame
+        if header[0] == 0 {
+            break;
+        }
+        let id = [header[0], header[1], header[2], header[3]];
+        let size = if tag.major_version == 4 {
+            syncsafe(&header[4..8])
+        } else {
+            read_u32(&header[4..8])
+        };
+        let start = pos + HEADER_LEN;
+        let end = start.saturating_add(size);
+        let data = &body[start..end.min(body.len())];
+
+        frames.push(Frame {
+            id,
+            offset: to_file(pos),
+            length: to_file(end) - to_file(pos),
+            content: frame_content(tag.major_version, header[9], data),
+            complete: end <= body.len(),
+        });
+        pos = end;
+    }
+    frames
+}
+
+/// Strips the fields announced by the format flags from the frame data
+fn frame_content(major_version: u8, flags: u8, data: &[u8]) -> Option<Vec<u8>> {
+    if major_version == 3 {
+        if flags & (V3_COMPRESSED | V3_ENCRYPTED) != 0 {
+            return None;
+        }
+        let skip = if flags & V3_GROUPING != 0 { 1 } else { 0 };
+        return data.get(skip..).map(<[u8]>::to_vec);
+    }
+
+    if flags & (V4_COMPRESSED | V4_ENCRYPTED) != 0 {
+        return None;
+    }
+    let mut skip = 0;
+    if flags & V4_GROUPING != 0 {
+        skip += 1;
+    }
+    if flags & V4_DATA_LENGTH != 0 {
+        skip += 4;
+    }
+    let data = data.get(skip..)?;
+    if flags & V4_UNSYNC != 0 {
+        Some(resync(data))
+    } else {
+        Some(data.to_vec())
+    }
+}
+
+/// Skips a string terminated according to its text encoding
+fn skip_text(buf: &[u8], encoding: u8) -> Option<&[u8]> {
+    if encoding == 1 || encoding == 2 {
+        // UTF-16 strings end with two zero bytes on a character boundary
+        let end = buf.chunks_exact(2).position(|pair| pair == [0, 0])?;
+        buf.get(end * 2 + 2..)
+    } else {
+        let end = buf.iter().position(|&b| b == 0)?;
+        buf.get(end + 1..)
+    }
+}
+
+/// Returns the encapsulated object of a `GEOB` frame holding a manifest store
+fn c2pa_object(content: &[u8]) -> Option<&[u8]> {
+    let encoding = *content.first()?;
+    let rest = &content[1..];
+    let mime_end = rest.iter().position(|&b| b == 0)?;
+    if &rest[..mime_end] != C2PA_MIME {
+        return None;
+    }
+    // Then come the file name and content description
+    let rest = skip_text(&rest[mime_end + 1..], encoding)?;
+    skip_text(rest, encoding)
+}
+
+/// Returns the manifest store held by the `GEOB` frame at `location`
+pub fn store_data(buf: &[u8], location: Location) -> Option<Vec<u8>> {
+    let tag = read_tag(buf)?;
+    let frame = read_frames(&tag, buf)
+        .into_iter()
+        .find(|frame| frame.offset == location.offset && frame.id == GEOB)?;
+    c2pa_object(frame.content.as_ref()?).map(<[u8]>::to_vec)
+}
+
+/// Reads the ID3v2 tag at the start of the file
+///
+/// The tag is only inspected once it has arrived in full, as unsynchronisation
+/// and extended headers make frame offsets unpredictable.
+#[derive(Debug, Default)]
+pub struct Mp3Scanner {
+    /// The store location is that of the whole `GEOB` frame, the provenance
+    /// reference comes from an XMP `PRIV` frame
+    pub findings: Findings,
+    finished: bool,
+}
+
+impl Mp3Scanner {
+    /// Inspects the tag at the start of `buf`, which is at absolute offset `base`,
+    /// and returns how many bytes can be dropped, possibly beyond the end of `buf`
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        if self.finished {
+            return buf.len();
+        }
+        // Nothing is consumed until the tag has been read, so `buf` starts the file
+        debug_assert_eq!(base, 0);
+        let tag = match read_tag(buf) {
+            Some(tag) => tag,
+            None => {
+                if buf.len() >= HEADER_LEN || eof {
+                    self.finished = true;
+                }
+                return 0;
+            }
+        };
+        if buf.len() < tag.len && tag.len <= MAX_TAG_LEN && !eof {
+            return 0;
+        }
+
+        for frame in read_frames(&tag, buf) {
+            let content = match &frame.content {
+                Some(content) => content,
+                None => continue,
+            };
+            if frame.id == GEOB {
+                self.inspect_object(&frame, content);
+            } else if frame.id == PRIV && content.starts_with(XMP_OWNER) {
+                self.findings.add_xmp(
+                    &content[XMP_OWNER.len()..],
+                    frame.offset + HEADER_LEN + XMP_OWNER.len(),
+                );
+            }
+        }
+        self.finished = true;
+        tag.len
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn inspect_object(&mut self, frame: &Frame, content: &[u8]) {
+        let object = match c2pa_object(content) {
+            Some(object) if jumbf::is_c2pa_store(object) => object,
+            _ => return,
+        };
+        self.findings.add_store(Location {
+            offset: frame.offset,
+            length: Some(frame.length),
+        });
+        if frame.complete {
+            if let Some(damage) = jumbf::truncation(object) {
+                self.findings.add_damage(frame.offset, damage);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::extract_store;
+
+    fn store() -> Vec<u8> {
+        let jpeg = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        extract_store(jpeg).unwrap().bytes
+    }
+
+    fn geob(store: &[u8]) -> Vec<u8> {
+        let mut content = vec![0];
+        content.extend_from_slice(C2PA_MIME);
+        content.extend_from_slice(b"\0c2pa\0c2pa manifest store\0");
+        content.extend_from_slice(store);
+        content
+    }
+
+    fn syncsafe_bytes(n: usize) -> [u8; 4] {
+        [
+            (n >> 21) as u8 & 0x7F,
+            (n >> 14) as u8 & 0x7F,
+            (n >> 7) as u8 & 0x7F,
+            n as u8 & 0x7F,
+        ]
+    }
+
+    fn unsync(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (i, &b) in data.iter().enumerate() {
+            out.push(b);
+            let next = data.get(i + 1).copied();
+            if b == 0xFF && (next.is_none() || next.unwrap_or(0) & 0xE0 == 0xE0 || next == Some(0))
+            {
+                out.push(0);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn finds_geob_frame_in_v4_tag() {
+        let store = store();
+        let content = geob(&store);
+        let mut asset = b"ID3\x04\0\0".to_vec();
+        asset.extend_from_slice(&syncsafe_bytes(HEADER_LEN + content.len()));
+        asset.extend_from_slice(b"GEOB");
+        asset.extend_from_slice(&syncsafe_bytes(content.len()));
+        asset.extend_from_slice(&[0, 0]);
+        asset.extend_from_slice(&content);
+        asset.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+
+        let mut scanner = Mp3Scanner::default();
+        scanner.scan(&asset, 0, true);
+
+        let location = scanner.findings.store().unwrap();
+        assert_eq!(location.offset, HEADER_LEN);
+        assert_eq!(store_data(&asset, location), Some(store));
+    }
+
+    #[test]
+    fn reads_unsynchronised_v3_tag_with_extended_header() {
+        let store = store();
+        let content = geob(&store);
+        let mut frames = b"\0\0\0\x06\0\0\0\0\0\0".to_vec();
+        frames.extend_from_slice(b"GEOB");
+        frames.extend_from_slice(&(content.len() as u32).to_be_bytes());
+        frames.extend_from_slice(&[0, 0]);
+        frames.extend_from_slice(&content);
+        let frames = unsync(&frames);
+        let mut asset = vec![b'I', b'D', b'3', 3, 0, FLAG_UNSYNC | FLAG_EXTENDED];
+        asset.extend_from_slice(&syncsafe_bytes(frames.len()));
+        asset.extend_from_slice(&frames);
+
+        let mut scanner = Mp3Scanner::default();
+        scanner.scan(&asset, 0, true);
+
+        let location = scanner.findings.store().unwrap();
+        assert_eq!(location.offset, 2 * HEADER_LEN);
+        assert_eq!(location.length, Some(asset.len() - location.offset));
+        assert_eq!(store_data(&asset, location), Some(store));
+    }
+}
@@ -0,0 +1,805 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! PDF reader that follows the document catalog to the embedded file holding a
+//! C2PA Manifest Store.
+//!
+//! The store is attached as an associated file (`/AF`) of the catalog, whose file
+//! specification has an `/AFRelationship` of `/C2PA_Manifest` and whose embedded
+//! file stream has the `application/c2pa` subtype. The embedded files name tree
+//! is searched as well for writers that only register it there.
+
+use crate::detection::{Findings, Location};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use twoway::{find_bytes, rfind_bytes};
+
+const HEADER: &[u8] = b"%PDF-";
+const STARTXREF: &[u8] = b"startxref";
+
+const C2PA_RELATIONSHIP: &[u8] = b"C2PA_Manifest";
+const C2PA_SUBTYPE: &[u8] = b"application/c2pa";
+
+/// How far from the end of the file `startxref` is looked for
+const TRAILER_SEARCH_LEN: usize = 4096;
+/// Bounds nesting of arrays, dictionaries and name tree nodes
+const MAX_DEPTH: usize = 32;
+
+pub fn is_pdf(buf: &[u8]) -> bool {
+    buf.starts_with(HEADER)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Real(f64),
+    Name(Vec<u8>),
+    String(Vec<u8>),
+    Array(Vec<Object>),
+    Dictionary(Vec<(Vec<u8>, Object)>),
+    Reference(u32, u16),
+}
+
+impl Object {
+    pub fn get(&self, key: &[u8]) -> Option<&Object> {
+        match self {
+            Object::Dictionary(entries) => entries
+                .iter()
+                .find(|(name, _)| name.as_slice() == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn as_name(&self) -> Option<&[u8]> {
+        match self {
+            Object::Name(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn as_integer(&self) -> Option<i64> {
+        match self {
+            Object::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+fn is_whitespace(b: u8) -> bool {
+    b"\0\t\n\x0c\r ".contains(&b)
+}
+
+fn is_delimiter(b: u8) -> bool {
+    b"()<>[]{}/%".contains(&b)
+}
+
+fn is_regular(b: u8) -> bool {
+    !is_whitespace(b) && !is_delimiter(b)
+}
+
+struct Lexer<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(buf: &'a [u8], pos: usize) -> Self {
+        Lexer { buf, pos }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.buf.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b) = self.peek() {
+            if is_whitespace(b) {
+                self.pos += 1;
+            } else if b == b'%' {
+                while !matches!(self.peek(), None | Some(b'\r') | Some(b'\n')) {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Reads a run of regular characters, such as a keyword or number
+    fn token(&mut self) -> &'a [u8] {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self.peek().is_some_and(is_regular) {
+            self.pos += 1;
+        }
+        &self.buf[start..self.pos]
+    }
+
+    fn keyword(&mut self, keyword: &[u8]) -> Option<()> {
+        if self.token() == keyword {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn unsigned(&mut self) -> Option<u64> {
+        let token = self.token();
+        if token.is_empty() || !token.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        std::str::from_utf8(token).ok()?.parse().ok()
+    }
+
+    fn object(&mut self, depth: usize) -> Option<Object> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        self.skip_whitespace();
+        match self.peek()? {
+            b'/' => {
+                self.pos += 1;
+                Some(Object::Name(self.name()))
+            }
+            b'<' if self.buf.get(self.pos + 1) == Some(&b'<') => {
+                self.pos += 2;
+                self.dictionary(depth)
+            }
+            b'<' => {
+                self.pos += 1;
+                self.hex_string()
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.peek()? == b']' {
+                        self.pos += 1;
+                        return Some(Object::Array(items));
+                    }
+                    items.push(self.object(depth + 1)?);
+                }
+            }
+            b'(' => {
+                self.pos += 1;
+                self.literal_string()
+            }
+            _ => self.number_or_keyword(),
+        }
+    }
+
+    fn dictionary(&mut self, depth: usize) -> Option<Object> {
+        let mut entries = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek()? {
+                b'>' if self.buf.get(self.pos + 1) == Some(&b'>') => {
+                    self.pos += 2;
+                    return Some(Object::Dictionary(entries));
+                }
+                b'/' => {
+                    self.pos += 1;
+                    let key = self.name();
+                    let value = self.object(depth + 1)?;
+                    entries.push((key, value));
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Reads a name after its leading slash, resolving `#xx` escapes
+    fn name(&mut self) -> Vec<u8> {
+        let mut name = Vec::new();
+        while let Some(b) = self.peek().filter(|&b| is_regular(b)) {
+            self.pos += 1;
+            if b == b'#' {
+                let hex = self.buf.get(self.pos..self.pos + 2);
+                if let Some(value) =
+                    hex.and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+                {
+                    name.push(value);
+                    self.pos += 2;
+                    continue;
+                }
+            }
+            name.push(b);
+        }
+        name
+    }
+
+    fn hex_string(&mut self) -> Option<Object> {
+        let end = self.pos + self.buf[self.pos..].iter().position(|&b| b == b'>')?;
+        let digits: Vec<u8> = self.buf[self.pos..end]
+            .iter()
+            .filter_map(|&b| (b as char).to_digit(16).map(|d| d as u8))
+            .collect();
+        self.pos = end + 1;
+        Some(Object::String(
+            digits
+                .chunks(2)
+                .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+                .collect(),
+        ))
+    }
+
+    fn literal_string(&mut self) -> Option<Object> {
+        let mut value = Vec::new();
+        let mut nesting = 0;
+        loop {
+            let b = self.peek()?;
+            self.pos += 1;
+            match b {
+                b'\\' => {
+                    let escaped = self.peek()?;
+                    self.pos += 1;
+                    value.push(match escaped {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'b' => 0x08,
+                        b'f' => 0x0C,
+                        b'0'..=b'7' => {
+                            let mut code = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(d @ b'0'..=b'7') => {
+                                        code = code * 8 + (d - b'0') as u32;
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            code as u8
+                        }
+                        // Line continuation
+                        b'\r' | b'\n' => continue,
+                        other => other,
+                    });
+                }
+                b'(' => {
+                    nesting += 1;
+                    value.push(b);
+                }
+                b')' if nesting == 0 => return Some(Object::String(value)),
+                b')' => {
+                    nesting -= 1;
+                    value.push(b);
+                }
+                _ => value.push(b),
+            }
+        }
+    }
+
+    fn number_or_keyword(&mut self) -> Option<Object> {
+        let token = self.token();
+        match token {
+            b"true" => return Some(Object::Bool(true)),
+            b"false" => return Some(Object::Bool(false)),
+            b"null" => return Some(Object::Null),
+            _ => {}
+        }
+        let text = std::str::from_utf8(token).ok()?;
+        if let Ok(value) = text.parse::<i64>() {
+            // An integer may start an indirect reference, `num gen R`
+            let after_number = self.pos;
+            if let Some(reference) = self.reference_suffix(value) {
+                return Some(reference);
+            }
+            self.pos = after_number;
+            return Some(Object::Integer(value));
+        }
+        text.parse::<f64>().ok().map(Object::Real)
+    }
+
+    fn reference_suffix(&mut self, num: i64) -> Option<Object> {
+        let gen = self.unsigned()?;
+        self.keyword(b"R")?;
+        Some(Object::Reference(
+            u32::try_from(num).ok()?,
+            u16::try_from(gen).ok()?,
+        ))
+    }
+}
+
+/// An indirect object read from the file
+#[derive(Debug, Clone)]
+pub struct Indirect<'a> {
+    pub offset: usize,
+    pub object: Object,
+    /// Stream data, when the object is a stream
+    pub stream: Option<&'a [u8]>,
+}
+
+impl<'a> Indirect<'a> {
+    /// Whether the stream data is stored as-is
+    fn is_unfiltered(&self) -> bool {
+        self.object.get(b"Filter").is_none()
+    }
+
+    fn end(&self, buf: &[u8]) -> usize {
+        match self.stream {
+            Some(data) => data.as_ptr() as usize - buf.as_ptr() as usize + data.len(),
+            None => self.offset,
+        }
+    }
+}
+
+/// Cross-reference information gathered from the whole file
+pub struct Document<'a> {
+    buf: &'a [u8],
+    /// Object offsets from the cross-reference tables, newest revision first
+    offsets: HashMap<u32, usize>,
+    root: Option<Object>,
+    /// Object offsets found by scanning the file for `obj` keywords, built on demand
+    scanned: Option<HashMap<u32, usize>>,
+}
+
+impl<'a> Document<'a> {
+    pub fn parse(buf: &'a [u8]) -> Self {
+        let mut document = Document {
+            buf,
+            offsets: HashMap::new(),
+            root: None,
+            scanned: None,
+        };
+
+        let mut next = document.startxref();
+        let mut visited = HashSet::new();
+        while let Some(offset) = next {
+            if !visited.insert(offset) {
+                break;
+            }
+            next = document.read_xref_section(offset);
+        }
+        document
+    }
+
+    fn startxref(&self) -> Option<usize> {
+        let tail_start = self.buf.len().saturating_sub(TRAILER_SEARCH_LEN);
+        let pos = tail_start + rfind_bytes(&self.buf[tail_start..], STARTXREF)?;
+        let mut lexer = Lexer::new(self.buf, pos + STARTXREF.len());
+        lexer.unsigned().map(|offset| offset as usize)
+    }
+
+    /// Reads a cross-reference table or stream and its trailer, returning the
+    /// offset of the previous section
+    fn read_xref_section(&mut self, offset: usize) -> Option<usize> {
+        let mut lexer = Lexer::new(self.buf, offset);
+        let trailer = if lexer.token() == b"xref" {
+            loop {
+                let checkpoint = lexer.pos;
+                if lexer.token() == b"trailer" {
+                    break;
+                }
+                lexer.pos = checkpoint;
+                let first = lexer.unsigned()?;
+                let count = lexer.unsigned()?;
+                for num in first..first.saturating_add(count) {
+                    let entry_offset = lexer.unsigned()?;
+                    lexer.unsigned()?;
+                    let in_use = lexer.token() == b"n";
+                    if in_use && num <= u32::MAX as u64 {
+                        self.offsets
+                            .entry(num as u32)
+                            .or_insert(entry_offset as usize);
+                    }
+                }
+            }
+            lexer.object(0)?
+        } else {
+            // A cross-reference stream, whose compressed entries we cannot read, so
+            // objects will be located by scanning
+            self.read_indirect(offset)?.object
+        };
+
+        if self.root.is_none() {
+            self.root = trailer.get(b"Root").cloned();
+        }
+        trailer
+            .get(b"Prev")
+            .and_then(Object::as_integer)
+            .map(|prev| prev as usize)
+    }
+
+    /// Reads `num gen obj` at `offset` and the object, leaving `lexer` after it
+    fn read_object(&self, offset: usize) -> Option<(Object, Lexer<'a>)> {
+        let mut lexer = Lexer::new(self.buf, offset);
+        lexer.unsigned()?;
+        lexer.unsigned()?;
+        lexer.keyword(b"obj")?;
+        let object = lexer.object(0)?;
+        Some((object, lexer))
+    }
+
+    /// Reads `num gen obj` at `offset`, followed by the object and its stream data
+    fn read_indirect(&self, offset: usize) -> Option<Indirect<'a>> {
+        let (object, mut lexer) = self.read_object(offset)?;
+
+        let stream = if lexer.token() == b"stream" {
+            // The keyword is followed by CRLF or LF
+            let mut start = lexer.pos;
+            if self.buf.get(start) == Some(&b'\r') {
+                start += 1;
+            }
+            if self.buf.get(start) == Some(&b'\n') {
+                start += 1;
+            }
+            let declared = match object.get(b"Length") {
+                Some(Object::Integer(len)) => Some(*len as usize),
+                Some(reference @ Object::Reference(..)) => self
+                    .resolve_shallow(reference)
+                    .and_then(|len| len.as_integer())
+                    .map(|len| len as usize),
+                _ => None,
+            };
+            let rest = self.buf.get(start..)?;
+            let len = declared
+                .filter(|&len| len <= rest.len())
+                .or_else(|| find_bytes(rest, b"endstream"))?;
+            Some(&rest[..len])
+        } else {
+            None
+        };
+
+        Some(Indirect {
+            offset,
+            object,
+            stream,
+        })
+    }
+
+    /// Loads object `num`, falling back to a scan of the file when the
+    /// cross-reference tables do not lead to it
+    pub fn load(&mut self, num: u32) -> Option<Indirect<'a>> {
+        if let Some(&offset) = self.offsets.get(&num) {
+            if self.object_number_at(offset) == Some(num) {
+                if let Some(indirect) = self.read_indirect(offset) {
+                    return Some(indirect);
+                }
+            }
+        }
+        let offset = *self.scanned().get(&num)?;
+        self.read_indirect(offset)
+    }
+
+    /// Resolves a reference without falling back to scanning, as used while
+    /// reading stream lengths
+    ///
+    /// The stream data of the referenced object is not read, and a stream is not
+    /// accepted, so that a length referring back to its own stream cannot recurse.
+    fn resolve_shallow(&self, object: &Object) -> Option<Object> {
+        match object {
+            Object::Reference(num, _) => {
+                let offset = *self.offsets.get(num)?;
+                let (object, mut lexer) = self.read_object(offset)?;
+                Some(object).filter(|_| lexer.token() != b"stream")
+            }
+            other => Some(other.clone()),
+        }
+    }
+
+    fn resolve(&mut self, object: &Object) -> Option<Object> {
+        match object {
+            Object::Reference(num, _) => self.load(*num).map(|indirect| indirect.object),
+            other => Some(other.clone()),
+        }
+    }
+
+    fn object_number_at(&self, offset: usize) -> Option<u32> {
+        let mut lexer = Lexer::new(self.buf, offset);
+        let num = lexer.unsigned()?;
+        lexer.unsigned()?;
+        lexer.keyword(b"obj")?;
+        Some(num as u32)
+    }
+
+    fn scanned(&mut self) -> &HashMap<u32, usize> {
+        let buf = self.buf;
+        self.scanned.get_or_insert_with(|| scan_objects(buf))
+    }
+
+    fn catalog(&mut self) -> Option<Object> {
+        if let Some(root) = self.root.clone() {
+            if let Some(catalog) = self.resolve(&root) {
+                return Some(catalog);
+            }
+        }
+        // Without a usable trailer, use the last catalog in the file
+        let mut candidates: Vec<(u32, usize)> = self
+            .scanned()
+            .iter()
+            .map(|(&num, &offset)| (num, offset))
+            .collect();
+        candidates.sort_by_key(|&(_, offset)| std::cmp::Reverse(offset));
+        candidates.into_iter().find_map(|(_, offset)| {
+            let object = self.read_indirect(offset)?.object;
+            match object.get(b"Type").and_then(Object::as_name) {
+                Some(b"Catalog") => Some(object),
+                _ => None,
+            }
+        })
+    }
+
+    /// Finds the embedded file streams holding manifest stores, associated
+    /// files first
+    pub fn find_manifest_stores(&mut self) -> Vec<Indirect<'a>> {
+        let mut stores = Vec::new();
+        let catalog = match self.catalog() {
+            Some(catalog) => catalog,
+            None => return stores,
+        };
+
+        if let Some(Object::Array(files)) = catalog.get(b"AF").and_then(|af| self.resolve(af)) {
+            for file in &files {
+                self.add_c2pa_stream(file, &mut stores);
+            }
+        }
+
+        let root = catalog
+            .get(b"Names")
+            .and_then(|names| self.resolve(names))
+            .and_then(|names| names.get(b"EmbeddedFiles").cloned());
+        if let Some(root) = root {
+            let mut visited = HashSet::new();
+            self.search_name_tree(&root, 0, &mut visited, &mut stores);
+        }
+        stores
+    }
+
+    /// Searches the name tree node `node`, skipping the nodes already in `visited`
+    /// by object number, as kids may be shared or form a loop
+    fn search_name_tree(
+        &mut self,
+        node: &Object,
+        depth: usize,
+        visited: &mut HashSet<u32>,
+        stores: &mut Vec<Indirect<'a>>,
+    ) {
+        if depth > MAX_DEPTH {
+            return;
+        }
+        if let Object::Reference(num, _) = node {
+            if !visited.insert(*num) {
+                return;
+            }
+        }
+        let node = match self.resolve(node) {
+            Some(node) => node,
+            None => return,
+        };
+        if let Some(Object::Array(names)) = node.get(b"Names") {
+            // Alternating keys and file specifications
+            for file in names.iter().skip(1).step_by(2) {
+                self.add_c2pa_stream(file, stores);
+            }
+        }
+        if let Some(Object::Array(kids)) = node.get(b"Kids") {
+            for kid in kids {
+                self.search_name_tree(kid, depth + 1, visited, stores);
+            }
+        }
+    }
+
+    /// Adds the stream of a file specification describing a manifest store,
+    /// unless it was reached before
+    fn add_c2pa_stream(&mut self, file: &Object, stores: &mut Vec<Indirect<'a>>) {
+        if let Some(stream) = self.c2pa_stream(file) {
+            if stores.iter().all(|store| store.offset != stream.offset) {
+                stores.push(stream);
+            }
+        }
+    }
+
+    /// Returns the embedded file stream of a file specification describing a manifest store
+    fn c2pa_stream(&mut self, file: &Object) -> Option<Indirect<'a>> {
+        let spec = self.resolve(file)?;
+        let stream_ref = spec.get(b"EF")?.clone();
+        let stream_ref = self.resolve(&stream_ref)?.get(b"F")?.clone();
+        let num = match stream_ref {
+            Object::Reference(num, _) => num,
+            _ => return None,
+        };
+        let stream = self.load(num)?;
+
+        let relationship = spec.get(b"AFRelationship").and_then(Object::as_name);
+        let subtype = stream.object.get(b"Subtype").and_then(Object::as_name);
+        if relationship == Some(C2PA_RELATIONSHIP) || subtype == Some(C2PA_SUBTYPE) {
+            Some(stream)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the catalog's XMP metadata stream along with its offset, when
+    /// stored unfiltered
+    pub fn xmp_packet(&mut self) -> Option<(&'a [u8], usize)> {
+        let catalog = self.catalog()?;
+        let num = match catalog.get(b"Metadata")? {
+            Object::Reference(num, _) => *num,
+            _ => return None,
+        };
+        let metadata = self.load(num)?;
+        let data = metadata.stream.filter(|_| metadata.is_unfiltered())?;
+        Some((data, data.as_ptr() as usize - self.buf.as_ptr() as usize))
+    }
+}
+
+/// Returns the manifest store held by the embedded file stream at `location`,
+/// when it is stored unfiltered
+pub fn store_data(buf: &[u8], location: Location) -> Option<&[u8]> {
+    let stream = Document::parse(buf).read_indirect(location.offset)?;
+    stream.stream.filter(|_| stream.is_unfiltered())
+}
+
+/// Indexes every `num gen obj` in the file, later definitions replacing earlier ones
+fn scan_objects(buf: &[u8]) -> HashMap<u32, usize> {
+    let mut objects = HashMap::new();
+    let mut from = 0;
+    while let Some(pos) = find_bytes(&buf[from..], b"obj") {
+        let keyword = from + pos;
+        from = keyword + 3;
+        if buf.get(from).is_some_and(|&b| is_regular(b)) {
+            continue;
+        }
+        // Walk back over `gen` and `num`
+        let mut start = keyword;
+        for _ in 0..2 {
+            while start > 0 && is_whitespace(buf[start - 1]) {
+                start -= 1;
+            }
+            let end = start;
+            while start > 0 && buf[start - 1].is_ascii_digit() {
+                start -= 1;
+            }
+            if start == end {
+                start = usize::MAX;
+                break;
+            }
+        }
+        if start == usize::MAX || (start > 0 && is_regular(buf[start - 1])) {
+            continue;
+        }
+        let mut lexer = Lexer::new(buf, start);
+        if let Some(num) = lexer.unsigned().filter(|&num| num <= u32::MAX as u64) {
+            objects.insert(num as u32, start);
+        }
+    }
+    objects
+}
+
+/// Reads the whole PDF once the end of the file is available
+#[derive(Debug, Default)]
+pub struct PdfScanner {
+    /// The store location covers the embedded file stream object up to the end of its data
+    pub findings: Findings,
+    finished: bool,
+}
+
+impl PdfScanner {
+    /// The cross-reference data lives at the end of the file, so nothing is
+    /// scanned (or dropped) until `eof` is set
+    pub fn scan(&mut self, buf: &[u8], _base: usize, eof: bool) -> usize {
+        if !eof {
+            return 0;
+        }
+        let mut document = Document::parse(buf);
+        for stream in document.find_manifest_stores() {
+            self.findings.add_store(Location {
+                offset: stream.offset,
+                length: Some(stream.end(buf) - stream.offset),
+            });
+            if self.findings.is_settled() {
+                break;
+            }
+        }
+        if let Some((packet, offset)) = document.xmp_packet() {
+            self.findings.add_xmp(packet, offset);
+        }
+        self.finished = true;
+        buf.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::{detect, DetectionResult, Format};
+
+    #[test]
+    fn finds_associated_file() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/sample.pdf");
+
+        let mut scanner = PdfScanner::default();
+        scanner.scan(asset, 0, true);
+
+        let store = scanner.findings.store().unwrap();
+        assert_eq!(store.offset, 226_083);
+        assert!(crate::jumbf::is_c2pa_store(
+            &asset[store.offset + store.length.unwrap() - 11_567..]
+        ));
+    }
+
+    /// Builds a file with the numbered `objects`, each followed by `suffix`, and an
+    /// xref table whose trailer names object 1 as the catalog
+    fn build(objects: &[&[u8]], suffix: &[u8]) -> Vec<u8> {
+        let mut pdf = b"%PDF-1.7\n".to_vec();
+        let mut offsets = Vec::new();
+        for (num, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n", num + 1).as_bytes());
+            pdf.extend_from_slice(object);
+            pdf.extend_from_slice(suffix);
+            pdf.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = pdf.len();
+        pdf.extend_from_slice(
+            format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+        );
+        for offset in &offsets {
+            pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend_from_slice(
+            format!(
+                "trailer\n<</Size {}/Root 1 0 R>>\nstartxref\n",
+                objects.len() + 1
+            )
+            .as_bytes(),
+        );
+        pdf.extend_from_slice(format!("{}\n%%EOF\n", xref).as_bytes());
+        pdf
+    }
+
+    #[test]
+    fn survives_self_referencing_lengths() {
+        // Object 1 takes its length from itself, objects 2 and 3 from each other
+        let pdf = build(
+            &[
+                b"<</Length 1 0 R/Type/Catalog>>",
+                b"<</Length 3 0 R>>",
+                b"<</Length 2 0 R>>",
+            ],
+            b"\nstream\ndata\nendstream",
+        );
+
+        let mut document = Document::parse(&pdf);
+        for num in 1..=3 {
+            assert_eq!(document.load(num).unwrap().stream, Some(&b"data\n"[..]));
+        }
+        let mut scanner = PdfScanner::default();
+        scanner.scan(&pdf, 0, true);
+        assert_eq!(scanner.findings.store(), None);
+    }
+
+    #[test]
+    fn visits_shared_name_tree_kids_once() {
+        // Each level of the tree lists the same node three times
+        let pdf = build(
+            &[
+                b"<</Type/Catalog/Names 2 0 R>>",
+                b"<</EmbeddedFiles 3 0 R>>",
+                b"<</Kids [3 0 R 3 0 R 3 0 R]>>",
+            ],
+            b"",
+        );
+
+        assert_eq!(
+            detect(&pdf),
+            DetectionResult::NotPresent {
+                format: Format::Pdf
+            }
+        );
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Planning of the byte ranges to request when only the head of an asset has
+//! been downloaded.
+//!
+//! The container structures found in the head (segment, box and chunk lengths,
+//! IFD offsets) tell where the manifest store is and how large it is, so the
+//! rest of it can be fetched with precise HTTP `Range` requests.
+
+use crate::detection::{Format, Scanner};
+use crate::{bmff, flac, jpeg, jumbf, jxl, png, riff};
+use serde::Serialize;
+use std::convert::TryFrom;
+
+/// A contiguous span of bytes in the asset
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub offset: usize,
+    pub length: usize,
+}
+
+impl ByteRange {
+    pub fn new(offset: usize, length: usize) -> Self {
+        ByteRange { offset, length }
+    }
+
+    pub fn end(&self) -> usize {
+        self.offset.saturating_add(self.length)
+    }
+
+    /// Appends a range to `ranges`, merging it into the last one when they are adjacent
+    pub fn extend(ranges: &mut Vec<ByteRange>, offset: usize, length: usize) {
+        match ranges.last_mut() {
+            Some(last) if last.end() == offset => last.length = last.length.saturating_add(length),
+            _ => ranges.push(ByteRange::new(offset, length)),
+        }
+    }
+}
+
+/// Where the manifest store of a partially downloaded asset is
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RangePlan {
+    /// The store lies exactly within these ranges
+    Located {
+        format: Format,
+        ranges: Vec<ByteRange>,
+    },
+    /// The head does not reach the store, which, if present, lies within these
+    /// ranges, or for TIFF, is pointed to by the IFDs they hold
+    Candidates {
+        format: Format,
+        ranges: Vec<ByteRange>,
+    },
+    /// The asset has no embedded store
+    NotPresent { format: Format },
+}
+
+/// Plans the ranges holding the manifest store of an asset of `total_size`
+/// bytes, given its first bytes
+pub fn plan_ranges(head: &[u8], total_size: usize) -> RangePlan {
+    let head = &head[..head.len().min(total_size)];
+    let mut scanner = Scanner::for_header(head);
+    let next = scanner.scan(head, 0, head.len() == total_size);
+    let format = scanner.format();
+    let findings = scanner.findings();
+    let clip = |range: ByteRange| {
+        ByteRange::new(
+            range.offset,
+            range.length.min(total_size.saturating_sub(range.offset)),
+        )
+    };
+    let rest_from = |offset: usize| RangePlan::Candidates {
+        format,
+        ranges: vec![ByteRange::new(offset, total_size.saturating_sub(offset))],
+    };
+
+    if let Some(store) = findings.store() {
+        let ranges = match (format, store.length) {
+            (_, Some(length)) => vec![ByteRange::new(store.offset, length)],
+            (Format::Jpeg, None) => jpeg::store_ranges(head, store.offset),
+            // A blind search finds the UUID in the description box, 16 bytes into the superbox
+            (_, None) => match superbox_range(head, store.offset.saturating_sub(16)) {
+                Some(range) => vec![range],
+                None => return rest_from(store.offset.saturating_sub(16)),
+            },
+        };
+        return RangePlan::Located {
+            format,
+            ranges: ranges.into_iter().map(clip).collect(),
+        };
+    }
+    if let Some((offset, _)) = findings.damage() {
+        return rest_from(offset);
+    }
+    if scanner.is_finished() || next >= total_size {
+        return RangePlan::NotPresent { format };
+    }
+
+    // The scanner stopped at a structure running past the head, which may be the store
+    if let Some(range) = pending_store(&scanner, head, next) {
+        return RangePlan::Located {
+            format,
+            ranges: vec![clip(range)],
+        };
+    }
+    // The IFDs read so far do not say where the store is, but those past the head may
+    if let Scanner::Tiff(tiff) = &scanner {
+        if let Some(ifds) = tiff.pending_ifds() {
+            let ranges: Vec<ByteRange> = ifds
+                .into_iter()
+                .filter(|range| range.offset < total_size)
+                .map(clip)
+                .collect();
+            return if ranges.is_empty() {
+                RangePlan::NotPresent { format }
+            } else {
+                RangePlan::Candidates { format, ranges }
+            };
+        }
+    }
+    rest_from(next)
+}
+
+/// Range of the store `scanner` stopped at, `next` bytes into `head`, because it
+/// runs past the end of `head`
+///
+/// The TIFF walk keeps the whole head, and its IFDs tell where the store is.
+pub fn pending_store(scanner: &Scanner, head: &[u8], next: usize) -> Option<ByteRange> {
+    let pending = head.get(next..).unwrap_or_default();
+    match scanner {
+        Scanner::Png(_) => png::pending_store(pending, next),
+        Scanner::Bmff(_) => bmff::pending_store(pending, next),
+        Scanner::Riff(_) => riff::pending_store(pending, next),
+        Scanner::Jxl(_) => jxl::pending_store(pending, next),
+        Scanner::Flac(_) => flac::pending_store(pending, next),
+        Scanner::Tiff(tiff) => tiff.pending_store(),
+        _ => None,
+    }
+}
+
+fn superbox_range(head: &[u8], offset: usize) -> Option<ByteRange> {
+    let header = jumbf::read_box_header(head.get(offset..)?)?;
+    if header.box_type != jumbf::JUMB {
+        return None;
+    }
+    let length = header
+        .box_len
+        .map_or(usize::MAX, |len| usize::try_from(len).unwrap_or(usize::MAX));
+    Some(ByteRange::new(offset, length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::extract_store;
+    use crate::tiff;
+
+    fn located(plan: RangePlan) -> Vec<ByteRange> {
+        match plan {
+            RangePlan::Located { ranges, .. } => ranges,
+            other => panic!("unexpected plan {:?}", other),
+        }
+    }
+
+    #[test]
+    fn plans_jpeg_segments_past_the_head() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        let whole = located(plan_ranges(asset, asset.len()));
+
+        // The head only holds the start of the first APP11 segment
+        let ranges = located(plan_ranges(&asset[..128], asset.len()));
+        assert_eq!(ranges, whole);
+        assert_eq!(ranges[0].offset, 20);
+    }
+
+    #[test]
+    fn plans_png_chunk_from_its_header() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/c2pa-actions-1.2.png");
+
+        let ranges = located(plan_ranges(&asset[..100], asset.len()));
+        assert_eq!(ranges, vec![ByteRange::new(33, 73904)]);
+    }
+
+    #[test]
+    fn plans_tiff_ifd_and_tag_value_past_the_head() {
+        let store = extract_store(include_bytes!(
+            "../../../tools/testing/fixtures/images/CAICAI.jpg"
+        ))
+        .unwrap()
+        .bytes;
+        let entry = |ifd: &mut Vec<u8>, value_offset: usize| {
+            ifd.extend_from_slice(&1u16.to_le_bytes());
+            ifd.extend_from_slice(&tiff::C2PA_TAG.to_le_bytes());
+            ifd.extend_from_slice(&7u16.to_le_bytes());
+            ifd.extend_from_slice(&(store.len() as u32).to_le_bytes());
+            ifd.extend_from_slice(&(value_offset as u32).to_le_bytes());
+            ifd.extend_from_slice(&0u32.to_le_bytes());
+        };
+
+        // The IFD follows the header, and the tag value the image data
+        let value_offset = 8 + 18 + 10_000;
+        let mut asset = b"II\x2a\0\x08\0\0\0".to_vec();
+        entry(&mut asset, value_offset);
+        asset.resize(value_offset, 0xAA);
+        asset.extend_from_slice(&store);
+        let value = ByteRange::new(value_offset, store.len());
+        assert_eq!(located(plan_ranges(&asset, asset.len())), vec![value]);
+        assert_eq!(
+            located(plan_ranges(&asset[..100], asset.len())),
+            vec![value]
+        );
+
+        // The IFD follows the image data and the tag value
+        let ifd_offset = 8 + 10_000 + store.len();
+        let mut asset = b"II\x2a\0".to_vec();
+        asset.extend_from_slice(&(ifd_offset as u32).to_le_bytes());
+        asset.resize(8 + 10_000, 0xAA);
+        asset.extend_from_slice(&store);
+        entry(&mut asset, 8 + 10_000);
+        assert_eq!(
+            plan_ranges(&asset[..100], asset.len()),
+            RangePlan::Candidates {
+                format: Format::Tiff,
+                ranges: vec![ByteRange::new(ifd_offset, 18)],
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_unread_tail_as_candidate() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/I.jpg");
+
+        assert_eq!(
+            plan_ranges(asset, asset.len()),
+            RangePlan::NotPresent {
+                format: Format::Jpeg
+            }
+        );
+        assert!(matches!(
+            plan_ranges(&asset[..10], asset.len()),
+            RangePlan::Candidates { .. }
+        ));
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! PNG chunk walker that locates the `caBX` chunk carrying a C2PA Manifest Store.
+
+use crate::crc32::crc32;
+use crate::detection::{Damage, Findings, Location};
+use crate::jumbf;
+use crate::plan::ByteRange;
+
+const SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+
+const CABX: [u8; 4] = *b"caBX";
+const ITXT: [u8; 4] = *b"iTXt";
+const IEND: [u8; 4] = *b"IEND";
+
+const XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+
+/// Length and type fields preceding the chunk data
+const HEADER_LEN: usize = 8;
+
+pub fn is_png(buf: &[u8]) -> bool {
+    buf.starts_with(&SIGNATURE)
+}
+
+/// A chunk read from the buffer, whose data may be truncated
+#[derive(Debug, Clone, Copy)]
+pub struct Chunk<'a> {
+    pub offset: usize,
+    pub chunk_type: [u8; 4],
+    pub data_len: usize,
+    /// Data bytes available in the buffer
+    pub data: &'a [u8],
+    /// The stored CRC, if the whole chunk is available
+    pub crc: Option<u32>,
+}
+
+impl<'a> Chunk<'a> {
+    /// Reads the chunk at the start of `buf`, located at absolute offset `offset`
+    pub fn read(buf: &'a [u8], offset: usize) -> Option<Self> {
+        let header = buf.get(..HEADER_LEN)?;
+        let data_len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let chunk_type = [header[4], header[5], header[6], header[7]];
+
+        let data_end = HEADER_LEN.saturating_add(data_len);
+        let data = &buf[HEADER_LEN..data_end.min(buf.len())];
+        let crc = buf
+            .get(data_end..data_end.saturating_add(4))
+            .map(|crc| u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]));
+
+        Some(Chunk {
+            offset,
+            chunk_type,
+            data_len,
+            data,
+            crc,
+        })
+    }
+
+    /// Total size of the chunk including its length, type and CRC fields
+    pub fn total_len(&self) -> usize {
+        self.data_len.saturating_add(HEADER_LEN + 4)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.crc.is_some()
+    }
+
+    /// CRC computed over the chunk type and data
+    pub fn computed_crc(&self) -> u32 {
+        crc32(&[&self.chunk_type, self.data])
+    }
+}
+
+/// Returns the range of the `caBX` chunk starting `buf`, located at absolute
+/// offset `offset`, going by its header alone
+pub fn pending_store(buf: &[u8], offset: usize) -> Option<ByteRange> {
+    let chunk = Chunk::read(buf, offset)?;
+    if chunk.chunk_type == CABX {
+        Some(ByteRange::new(offset, chunk.total_len()))
+    } else {
+        None
+    }
+}
+
+/// Returns the manifest store held by the `caBX` chunk at `location`
+pub fn store_data(buf: &[u8], location: Location) -> Option<&[u8]> {
+    let chunk = Chunk::read(buf.get(location.offset..)?, location.offset)?;
+    if chunk.is_complete() {
+        Some(chunk.data)
+    } else {
+        None
+    }
+}
+
+/// Resumable walk over the PNG chunks
+#[derive(Debug)]
+pub struct PngScanner {
+    /// The store location is that of the whole `caBX` chunk, the provenance
+    /// reference comes from an XMP `iTXt` chunk
+    pub findings: Findings,
+    /// Absolute offset of the next chunk
+    pos: usize,
+    finished: bool,
+}
+
+impl Default for PngScanner {
+    fn default() -> Self {
+        PngScanner {
+            findings: Findings::default(),
+            pos: SIGNATURE.len(),
+            finished: false,
+        }
+    }
+}
+
+impl PngScanner {
+    /// Walks the chunks in `buf`, located at absolute offset `base`, and returns
+    /// how many bytes can be dropped, possibly beyond the end of `buf`
+    ///
+    /// Chunks that need to be inspected are left for the next call until they are
+    /// complete, unless `eof` is set.
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        while !self.finished {
+            let rel = self.pos - base;
+            let chunk = match buf.get(rel..).and_then(|buf| Chunk::read(buf, self.pos)) {
+                Some(chunk) => chunk,
+                None => {
+                    self.finished = eof;
+                    return rel;
+                }
+            };
+
+            match chunk.chunk_type {
+                IEND => self.finished = true,
+                CABX | ITXT if !chunk.is_complete() && !eof => return rel,
+                CABX => self.inspect_store(&chunk),
+                ITXT => self.inspect_xmp(&chunk),
+                _ => {}
+            }
+            self.pos = self.pos.saturating_add(chunk.total_len());
+        }
+        self.pos - base
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn inspect_store(&mut self, chunk: &Chunk) {
+        if let Some(expected) = chunk.crc {
+            let actual = chunk.computed_crc();
+            if actual != expected {
+                self.findings
+                    .add_damage(chunk.offset, Damage::ChecksumMismatch { expected, actual });
+                self.finished = self.findings.is_settled();
+                return;
+            }
+        }
+        if jumbf::is_c2pa_store(chunk.data) {
+            self.findings.add_store(Location {
+                offset: chunk.offset,
+                length: Some(chunk.total_len()),
+            });
+            if let Some(damage) = jumbf::truncation(chunk.data).filter(|_| chunk.is_complete()) {
+                self.findings.add_damage(chunk.offset, damage);
+            }
+            self.finished = self.findings.is_settled();
+        }
+    }
+
+    fn inspect_xmp(&mut self, chunk: &Chunk) {
+        if !self.findings.wants_provenance() || !chunk.data.starts_with(XMP_KEYWORD) {
+            return;
+        }
+        // Compression flag and method, then the (null terminated) language tag and translated keyword
+        let rest = &chunk.data[XMP_KEYWORD.len()..];
+        if rest.len() < 2 || rest[0] != 0 {
+            return;
+        }
+        let mut text_start = XMP_KEYWORD.len() + 2;
+        for _ in 0..2 {
+            match chunk.data[text_start..].iter().position(|&b| b == 0) {
+                Some(end) => text_start += end + 1,
+                None => return,
+            }
+        }
+
+        self.findings.add_xmp(
+            &chunk.data[text_start..],
+            chunk.offset + HEADER_LEN + text_start,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(asset: &[u8]) -> Findings {
+        let mut scanner = PngScanner::default();
+        scanner.scan(asset, 0, true);
+        scanner.findings
+    }
+
+    #[test]
+    fn finds_store_in_cabx() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/c2pa-actions-1.2.png");
+
+        let store = scan(asset).store().unwrap();
+        assert_eq!(store.offset, 33);
+        assert_eq!(store.length, Some(73904));
+    }
+
+    #[test]
+    fn reports_crc_mismatch() {
+        let mut asset =
+            include_bytes!("../../../tools/testing/fixtures/images/crypto-social.png").to_vec();
+        asset[1000] ^= 0xFF;
+
+        let findings = scan(&asset);
+        assert_eq!(findings.store(), None);
+        assert!(matches!(
+            findings.damage(),
+            Some((33, Damage::ChecksumMismatch { .. }))
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! RIFF (WAV, AVI, WebP) chunk walker that locates the `C2PA` chunk carrying
+//! a C2PA Manifest Store.
+
+use crate::detection::{Damage, Findings, Location};
+use crate::jumbf;
+use crate::plan::ByteRange;
+
+const RIFF: [u8; 4] = *b"RIFF";
+const LIST: [u8; 4] = *b"LIST";
+const C2PA: [u8; 4] = *b"C2PA";
+/// XMP chunk ids used by WebP and WAV respectively
+const XMP: [[u8; 4]; 2] = [*b"XMP ", *b"_PMX"];
+/// AVI media data, which never holds metadata and is not worth descending into
+const MOVI: [u8; 4] = *b"movi";
+
+/// `RIFF` id, size and form type
+const FILE_HEADER_LEN: usize = 12;
+const CHUNK_HEADER_LEN: usize = 8;
+
+/// XMP chunks larger than this are not inspected
+const MAX_XMP_LEN: usize = 1024 * 1024;
+
+pub fn is_riff(buf: &[u8]) -> bool {
+    buf.starts_with(&RIFF)
+}
+
+fn is_fourcc(id: &[u8]) -> bool {
+    id.len() == 4 && id.iter().all(|&b| (0x20..0x7F).contains(&b))
+}
+
+/// Returns the range of the `C2PA` chunk starting `buf`, located at absolute
+/// offset `offset`, going by its header alone
+pub fn pending_store(buf: &[u8], offset: usize) -> Option<ByteRange> {
+    let header = buf.get(..CHUNK_HEADER_LEN)?;
+    if header[..4] != C2PA {
+        return None;
+    }
+    let size = read_u32(&header[4..8]) as usize;
+    // Including the pad byte after odd-length chunks
+    Some(ByteRange::new(offset, CHUNK_HEADER_LEN + size + size % 2))
+}
+
+/// Returns the manifest store held by the `C2PA` chunk at `location`
+pub fn store_data(buf: &[u8], location: Location) -> Option<&[u8]> {
+    let header = buf.get(location.offset..location.offset + CHUNK_HEADER_LEN)?;
+    let size = read_u32(&header[4..8]) as usize;
+    let start = location.offset + CHUNK_HEADER_LEN;
+    buf.get(start..start.checked_add(size)?)
+}
+
+/// Resumable walk over the RIFF chunks, descending into `LIST` chunks
+#[derive(Debug, Default)]
+pub struct RiffScanner {
+    /// The store location is that of the whole `C2PA` chunk
+    pub findings: Findings,
+    /// Absolute offset of the next chunk
+    pos: usize,
+    /// End of the data declared by the `RIFF` header, once known
+    end: Option<usize>,
+    /// Offset of the previous chunk when it had an odd length and may be followed by a pad byte
+    unpadded: Option<usize>,
+    finished: bool,
+}
+
+impl RiffScanner {
+    /// Walks the chunks in `buf`, located at absolute offset `base`, and returns
+    /// how many bytes can be dropped, possibly beyond the end of `buf`
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        while !self.finished {
+            let rel = self.pos - base;
+            let available = buf.get(rel..).unwrap_or_default();
+
+            let end = match self.end {
+                Some(end) => end,
+                None => {
+                    if available.len() < FILE_HEADER_LEN {
+                        self.finished = eof;
+                        return rel;
+                    }
+                    let size = read_u32(&available[4..8]);
+                    self.end = Some((size as usize).saturating_add(CHUNK_HEADER_LEN));
+                    self.pos += FILE_HEADER_LEN;
+                    continue;
+                }
+            };
+
+            if let Some(chunk_offset) = self.unpadded {
+                // Some writers omit the pad byte after odd-length chunks, in which
+                // case the next chunk id starts right away
+                let next = available.get(..5);
+                if next.is_none() && !eof {
+                    return rel;
+                }
+                let missing = match next {
+                    Some(next) => next[0] != 0 && is_fourcc(&next[..4]) && !is_fourcc(&next[1..]),
+                    None => available.is_empty() && self.pos < end,
+                };
+                self.unpadded = None;
+                if !missing {
+                    self.pos += 1;
+                }
+                if self.findings.contains_store(chunk_offset) {
+                    if missing {
+                        self.findings
+                            .add_damage(chunk_offset, Damage::MissingPadding);
+                    }
+                    self.finished = self.findings.is_settled();
+                }
+                continue;
+            }
+
+            if self.pos.saturating_add(CHUNK_HEADER_LEN) > end {
+                self.finished = true;
+                break;
+            }
+            if available.len() < CHUNK_HEADER_LEN {
+                self.finished = eof;
+                return rel;
+            }
+            let id = [available[0], available[1], available[2], available[3]];
+            let size = read_u32(&available[4..8]) as usize;
+            let chunk_len = size.saturating_add(CHUNK_HEADER_LEN);
+
+            if id == LIST {
+                if available.len() < FILE_HEADER_LEN && !eof {
+                    return rel;
+                }
+                if available.get(8..12) != Some(&MOVI[..]) {
+                    // Walk the sub-chunks as if they were siblings of the list
+                    self.pos += FILE_HEADER_LEN;
+                    continue;
+                }
+            }
+
+            let inspect = id == C2PA || (XMP.contains(&id) && size <= MAX_XMP_LEN);
+            if inspect {
+                if available.len() < chunk_len && !eof {
+                    return rel;
+                }
+                let data = &available[CHUNK_HEADER_LEN..available.len().min(chunk_len)];
+                self.inspect(&id, chunk_len, data);
+            }
+
+            if size % 2 == 1 {
+                self.unpadded = Some(self.pos);
+            } else if self.findings.is_settled() {
+                self.finished = true;
+            }
+            match self.pos.checked_add(chunk_len) {
+                Some(next) => self.pos = next,
+                None => self.finished = true,
+            }
+        }
+        self.pos - base
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn inspect(&mut self, id: &[u8; 4], chunk_len: usize, data: &[u8]) {
+        if *id == C2PA {
+            if jumbf::is_c2pa_store(data) {
+                self.findings.add_store(Location {
+                    offset: self.pos,
+                    length: Some(chunk_len),
+                });
+                if let Some(damage) =
+                    jumbf::truncation(data).filter(|_| data.len() + CHUNK_HEADER_LEN == chunk_len)
+                {
+                    self.findings.add_damage(self.pos, damage);
+                }
+            }
+        } else {
+            self.findings.add_xmp(data, self.pos + CHUNK_HEADER_LEN);
+        }
+    }
+}
+
+fn read_u32(buf: &[u8]) -> u32 {
+    u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_store_in_avi() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/sample.avi");
+
+        let mut scanner = RiffScanner::default();
+        scanner.scan(asset, 0, true);
+
+        assert_eq!(
+            scanner.findings.store(),
+            Some(Location {
+                offset: 742_478,
+                length: Some(14_397),
+            })
+        );
+    }
+
+    #[test]
+    fn reports_missing_padding() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/sample.avi");
+        // Drop the pad byte after the odd-length `C2PA` chunk and follow it with another chunk
+        let mut asset = asset[..asset.len() - 1].to_vec();
+        asset.extend_from_slice(b"JUNK\0\0\0\0");
+        let size = (asset.len() - 8) as u32;
+        asset[4..8].copy_from_slice(&size.to_le_bytes());
+
+        let mut scanner = RiffScanner::default();
+        scanner.scan(&asset, 0, true);
+
+        assert_eq!(scanner.findings.store(), None);
+        assert_eq!(
+            scanner.findings.damage(),
+            Some((742_478, Damage::MissingPadding))
+        );
+    }
+}
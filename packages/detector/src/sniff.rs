@@ -0,0 +1,242 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Identification of an asset's MIME type from its leading bytes, for when the
+//! `Content-Type` it was served with cannot be trusted.
+
+use crate::{bmff, flac, gif, jpeg, jumbf, jxl, mp3, pdf, png, riff, tiff, zip};
+use twoway::find_bytes;
+
+/// TIFF tag only present in DNG files
+const DNG_VERSION_TAG: u16 = 0xC612;
+
+/// How far into a text file the `<svg` root element is looked for
+const SVG_SEARCH_LEN: usize = 4096;
+
+/// Types declared by the `mimetype` entry of EPUB and OpenDocument files
+const DECLARED_ZIP_TYPES: [&str; 4] = [
+    "application/epub+zip",
+    "application/vnd.oasis.opendocument.presentation",
+    "application/vnd.oasis.opendocument.spreadsheet",
+    "application/vnd.oasis.opendocument.text",
+];
+
+/// Returns the MIME type, as expected by the toolkit, of the asset starting with `buf`
+///
+/// A few kilobytes are enough for every format, though a DNG is only told apart
+/// from a plain TIFF when its first IFD is included.
+pub fn sniff_format(buf: &[u8]) -> Option<&'static str> {
+    if jpeg::is_jpeg(buf) {
+        Some("image/jpeg")
+    } else if png::is_png(buf) {
+        Some("image/png")
+    } else if gif::is_gif(buf) {
+        Some("image/gif")
+    } else if riff::is_riff(buf) {
+        match buf.get(8..12)? {
+            b"WEBP" => Some("image/webp"),
+            b"WAVE" => Some("audio/wav"),
+            b"AVI " => Some("video/x-msvideo"),
+            _ => None,
+        }
+    } else if tiff::is_tiff(buf) {
+        if tiff::first_ifd_has_tag(buf, DNG_VERSION_TAG) {
+            Some("image/x-adobe-dng")
+        } else {
+            Some("image/tiff")
+        }
+    } else if jxl::is_jxl(buf) {
+        Some("image/jxl")
+    } else if bmff::is_bmff(buf) {
+        Some(bmff_type(buf))
+    } else if pdf::is_pdf(buf) {
+        Some("application/pdf")
+    } else if mp3::is_mp3(buf) {
+        Some("audio/mpeg")
+    } else if flac::is_flac(buf) {
+        Some("audio/flac")
+    } else if zip::is_zip(buf) {
+        zip_type(buf)
+    } else if jumbf::is_c2pa_store(buf) {
+        Some("application/c2pa")
+    } else if is_svg(buf) {
+        Some("image/svg+xml")
+    } else {
+        None
+    }
+}
+
+/// Picks the MIME type from the `ftyp` brands
+fn bmff_type(buf: &[u8]) -> &'static str {
+    if &buf[4..8] != b"ftyp" {
+        // QuickTime files may start without `ftyp`
+        return "video/quicktime";
+    }
+    let box_len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+    let ftyp = &buf[8..buf.len().min(box_len.max(16))];
+    let major = ftyp.get(..4).and_then(brand_type);
+    if let Some(mime_type) = major.filter(|&mime_type| mime_type != "image/heif") {
+        return mime_type;
+    }
+    // `mif1` only says the file is HEIF, a compatible brand may name the codec
+    let compatible = ftyp.get(8..).unwrap_or_default().chunks_exact(4);
+    compatible
+        .filter_map(brand_type)
+        .find(|mime_type| mime_type.starts_with("image/") && *mime_type != "image/heif")
+        .or(major)
+        .unwrap_or("video/mp4")
+}
+
+fn brand_type(brand: &[u8]) -> Option<&'static str> {
+    match brand {
+        b"avif" | b"avis" => Some("image/avif"),
+        b"heic" | b"heix" | b"heim" | b"heis" => Some("image/heic"),
+        b"mif1" | b"msf1" => Some("image/heif"),
+        b"qt  " => Some("video/quicktime"),
+        b"M4A " | b"M4B " => Some("audio/mp4"),
+        _ => None,
+    }
+}
+
+/// Picks the MIME type of a ZIP based document from its `mimetype` entry, or
+/// from the folder holding the parts of an Office Open XML file
+fn zip_type(buf: &[u8]) -> Option<&'static str> {
+    if let Some(declared) = zip::declared_mime_type(buf) {
+        return DECLARED_ZIP_TYPES
+            .iter()
+            .copied()
+            .find(|mime_type| mime_type.as_bytes() == declared);
+    }
+    zip::leading_entries(buf).find_map(|(name, _)| {
+        if name.starts_with(b"word/") {
+            Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document")
+        } else if name.starts_with(b"xl/") {
+            Some("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")
+        } else if name.starts_with(b"ppt/") {
+            Some("application/vnd.openxmlformats-officedocument.presentationml.presentation")
+        } else {
+            None
+        }
+    })
+}
+
+fn is_svg(buf: &[u8]) -> bool {
+    let text = buf.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(buf);
+    let start = text.iter().position(|b| !b.is_ascii_whitespace());
+    match start {
+        Some(start) if text[start] == b'<' => {
+            let window = &text[start..text.len().min(start + SVG_SEARCH_LEN)];
+            find_bytes(window, b"<svg").is_some()
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ftyp(brands: &[&[u8; 4]]) -> Vec<u8> {
+        let mut ftyp = ((16 + 4 * (brands.len() - 1)) as u32)
+            .to_be_bytes()
+            .to_vec();
+        ftyp.extend_from_slice(b"ftyp");
+        ftyp.extend_from_slice(brands[0]);
+        ftyp.extend_from_slice(&[0; 4]);
+        for brand in &brands[1..] {
+            ftyp.extend_from_slice(*brand);
+        }
+        ftyp
+    }
+
+    #[test]
+    fn sniffs_fixtures() {
+        let images = [
+            (
+                &include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg")[..],
+                "image/jpeg",
+            ),
+            (
+                &include_bytes!("../../../tools/testing/fixtures/images/crypto-social.png")[..],
+                "image/png",
+            ),
+            (
+                &include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.gif")[..],
+                "image/gif",
+            ),
+            (
+                &include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.svg")[..],
+                "image/svg+xml",
+            ),
+            (
+                &include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.docx")[..],
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            ),
+            (
+                &include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.epub")[..],
+                "application/epub+zip",
+            ),
+            (
+                &include_bytes!("../../../tools/testing/fixtures/images/sample.avi")[..],
+                "video/x-msvideo",
+            ),
+            (
+                &include_bytes!("../../../tools/testing/fixtures/images/sample.pdf")[..],
+                "application/pdf",
+            ),
+        ];
+
+        for (asset, mime_type) in &images {
+            assert_eq!(sniff_format(asset), Some(*mime_type));
+        }
+    }
+
+    #[test]
+    fn sniffs_bmff_brands() {
+        assert_eq!(sniff_format(&ftyp(&[b"heic", b"mif1"])), Some("image/heic"));
+        assert_eq!(sniff_format(&ftyp(&[b"mif1", b"heic"])), Some("image/heic"));
+        assert_eq!(sniff_format(&ftyp(&[b"mif1", b"miaf"])), Some("image/heif"));
+        assert_eq!(sniff_format(&ftyp(&[b"avif", b"mif1"])), Some("image/avif"));
+        assert_eq!(sniff_format(&ftyp(&[b"qt  "])), Some("video/quicktime"));
+        assert_eq!(sniff_format(&ftyp(&[b"M4A ", b"isom"])), Some("audio/mp4"));
+        assert_eq!(sniff_format(&ftyp(&[b"isom", b"mp41"])), Some("video/mp4"));
+    }
+
+    #[test]
+    fn sniffs_both_jxl_forms() {
+        assert_eq!(sniff_format(b"\xFF\x0A\xFA\x7F"), Some("image/jxl"));
+        assert_eq!(
+            sniff_format(b"\0\0\0\x0CJXL \x0D\x0A\x87\x0A"),
+            Some("image/jxl")
+        );
+    }
+
+    #[test]
+    fn sniffs_text_and_audio() {
+        let svg = b"\xEF\xBB\xBF<?xml version=\"1.0\"?>\n<!-- logo -->\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+
+        assert_eq!(sniff_format(svg), Some("image/svg+xml"));
+        assert_eq!(sniff_format(b"<html><body></body></html>"), None);
+        assert_eq!(sniff_format(b"ID3\x04\0\0\0\0\0\0"), Some("audio/mpeg"));
+        assert_eq!(sniff_format(&[0xFF, 0xFB, 0x90, 0x64]), Some("audio/mpeg"));
+        assert_eq!(sniff_format(b"fLaC\x00\0\0\x22"), Some("audio/flac"));
+        // AAC in ADTS framing uses layer bits of zero
+        assert_eq!(sniff_format(&[0xFF, 0xF1, 0x50, 0x80]), None);
+    }
+
+    #[test]
+    fn tells_dng_from_tiff() {
+        let mut tiff = b"II\x2a\0\x08\0\0\0\x01\0".to_vec();
+        tiff.extend_from_slice(&[0x00, 0x01, 3, 0, 1, 0, 0, 0, 16, 0, 0, 0]);
+        tiff.extend_from_slice(&[0; 4]);
+        let mut dng = tiff.clone();
+        dng[10..12].copy_from_slice(&DNG_VERSION_TAG.to_le_bytes());
+
+        assert_eq!(sniff_format(&tiff), Some("image/tiff"));
+        assert_eq!(sniff_format(&dng), Some("image/x-adobe-dng"));
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+use crate::detection::{DetectionResult, ScanReport, Scanner};
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Bytes read at a time from a seekable source
+const READ_LEN: usize = 1024 * 1024;
+
+/// Detector fed with consecutive chunks of an asset
+///
+/// Only the bytes a scanner still needs (an incomplete JPEG segment, the tail of a
+/// chunk that may hold the start of a marker) are kept between calls to `push`, and
+/// data the scanner is not interested in, such as PNG image data, is dropped as it arrives.
+#[derive(Debug, Default)]
+pub struct StreamDetector {
+    /// Bytes not yet consumed by the scanner, starting at absolute offset `base`
+    pending: Vec<u8>,
+    base: usize,
+    /// Bytes of upcoming input the scanner has asked to skip over
+    skip: usize,
+    scanner: Option<Scanner>,
+    /// Report every occurrence rather than settling on the first store
+    exhaustive: bool,
+}
+
+impl StreamDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Detector that keeps scanning to the end, for a `ScanReport` like `scan_all`
+    pub fn exhaustive() -> Self {
+        StreamDetector {
+            exhaustive: true,
+            ..Self::default()
+        }
+    }
+
+    /// Feeds the next chunk, returning `true` once the result is settled and
+    /// no more input is needed
+    pub fn push(&mut self, chunk: &[u8]) -> bool {
+        if self.is_finished() {
+            return true;
+        }
+        let skipped = self.skip.min(chunk.len());
+        self.skip -= skipped;
+        if self.skip > 0 {
+            return false;
+        }
+        self.pending.extend_from_slice(&chunk[skipped..]);
+
+        if self.scanner.is_none() {
+            if self.pending.len() < Scanner::HEADER_LEN {
+                return false;
+            }
+            self.start();
+        }
+        self.scan(false);
+        self.is_finished()
+    }
+
+    /// Takes up to `available` bytes of upcoming input off those the scanner has
+    /// asked to skip, returning how many the caller may jump over instead of pushing
+    ///
+    /// This lets a caller reading from a seekable source avoid reading data the
+    /// scanner has no use for, such as media data.
+    pub fn take_skip(&mut self, available: usize) -> usize {
+        let taken = self.skip.min(available);
+        self.skip -= taken;
+        taken
+    }
+
+    /// Scans whatever is left and returns the final result
+    pub fn finish(&mut self) -> DetectionResult {
+        self.finish_scan();
+        self.result()
+    }
+
+    /// Scans whatever is left and reports every occurrence found
+    pub fn finish_report(&mut self) -> ScanReport {
+        self.finish_scan();
+        match &self.scanner {
+            Some(scanner) => scanner.report(),
+            None => Scanner::for_header(&self.pending).report(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.scanner.as_ref().is_some_and(Scanner::is_finished)
+    }
+
+    /// The result based on the input seen so far
+    pub fn result(&self) -> DetectionResult {
+        match &self.scanner {
+            Some(scanner) => scanner.result(),
+            None => Scanner::for_header(&self.pending).result(),
+        }
+    }
+
+    /// Pushes the rest of `reader`, seeking past the data the scanner skips
+    fn read_from<R: Read + Seek>(&mut self, reader: &mut R) -> io::Result<()> {
+        let start = reader.stream_position()?;
+        let mut remaining = reader.seek(SeekFrom::End(0))?.saturating_sub(start);
+        reader.seek(SeekFrom::Start(start))?;
+
+        let mut chunk = vec![0; READ_LEN];
+        loop {
+            let skip = self.take_skip(remaining.min(usize::MAX as u64) as usize);
+            if skip > 0 {
+                reader.seek(SeekFrom::Current(skip as i64))?;
+                remaining -= skip as u64;
+            }
+            let read = reader.read(&mut chunk)?;
+            if read == 0 {
+                return Ok(());
+            }
+            remaining = remaining.saturating_sub(read as u64);
+            if self.push(&chunk[..read]) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn start(&mut self) {
+        let mut scanner = Scanner::for_header(&self.pending);
+        scanner.findings_mut().exhaustive = self.exhaustive;
+        self.scanner = Some(scanner);
+    }
+
+    fn finish_scan(&mut self) {
+        if self.scanner.is_none() {
+            self.start();
+        }
+        if self.is_finished() {
+            return;
+        }
+        match &mut self.scanner {
+            // The input ended within data being skipped, which never arrived
+            Some(scanner) if self.skip > 0 => {
+                scanner.scan(&[], self.base - self.skip, true);
+                self.skip = 0;
+            }
+            _ => self.scan(true),
+        }
+    }
+
+    fn scan(&mut self, eof: bool) {
+        if let Some(scanner) = &mut self.scanner {
+            let consumed = scanner.scan(&self.pending, self.base, eof);
+            if consumed >= self.pending.len() {
+                self.skip += consumed - self.pending.len();
+                self.pending.clear();
+            } else if scanner.is_finished() {
+                self.pending = Vec::new();
+            } else {
+                self.pending.drain(..consumed);
+            }
+            self.base += consumed;
+        }
+    }
+}
+
+/// Detects C2PA metadata in a seekable source such as a file, from its current
+/// position, without reading the data the scanner skips
+pub fn detect_reader<R: Read + Seek>(reader: &mut R) -> io::Result<DetectionResult> {
+    let mut detector = StreamDetector::new();
+    detector.read_from(reader)?;
+    Ok(detector.finish())
+}
+
+/// Same as `detect_reader`, reporting every occurrence like `scan_all`
+pub fn scan_all_reader<R: Read + Seek>(reader: &mut R) -> io::Result<ScanReport> {
+    let mut detector = StreamDetector::exhaustive();
+    detector.read_from(reader)?;
+    Ok(detector.finish_report())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::{detect, scan_all};
+    use std::io::Cursor;
+
+    #[test]
+    fn hands_skipped_ranges_back_to_the_caller() {
+        let mut asset = b"\0\0\0\x10ftypisom\0\0\0\0\0\0\0\x01mdat".to_vec();
+        asset.extend_from_slice(&(16u64 + 1_000_000).to_be_bytes());
+        asset.resize(asset.len() + 1_000_000, 0);
+        asset.extend_from_slice(b"\0\0\0\x08free");
+
+        let mut detector = StreamDetector::new();
+        let mut offset = 0;
+        let mut pushed = 0;
+        while offset < asset.len() {
+            offset += detector.take_skip(asset.len() - offset);
+            let end = asset.len().min(offset + 4096);
+            pushed += end - offset;
+            detector.push(&asset[offset..end]);
+            offset = end;
+        }
+
+        assert_eq!(detector.finish(), detect(&asset));
+        assert!(pushed < 3 * 4096);
+    }
+
+    #[test]
+    fn reads_seekable_source() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        let mut reader = Cursor::new(&asset[..]);
+
+        assert_eq!(detect_reader(&mut reader).unwrap(), detect(asset));
+        reader.set_position(0);
+        assert_eq!(scan_all_reader(&mut reader).unwrap(), scan_all(asset));
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Quick summary of the active manifest for display purposes.
+//!
+//! Only the JUMBF structure, the claim CBOR and the COSE signature headers are
+//! read. Neither the signature nor any hash is checked, so the summary must be
+//! presented as unverified.
+
+use crate::cbor::{self, Value};
+use crate::der;
+use crate::extract::extract_store;
+use crate::inspect::{parse_tree, JumbfBox};
+use crate::jumbf;
+use serde::Serialize;
+
+/// COSE header parameter holding the certificate chain
+const X5CHAIN: u64 = 33;
+
+/// Object identifier of the X.520 `commonName` attribute, 2.5.4.3
+const COMMON_NAME_OID: [u8; 3] = [0x55, 0x04, 0x03];
+
+const SIGNATURE_LABEL: &str = "c2pa.signature";
+
+/// What the active manifest says about itself
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestSummary {
+    /// Always `false`: nothing in the summary has been validated
+    pub verified: bool,
+    /// Label of the active manifest
+    pub active_manifest: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claim_generator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    /// Common name of the issuer of the signing certificate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Time from the signature's time-stamp token, as an ISO 8601 string
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signing_time: Option<String>,
+}
+
+/// Summarizes the active manifest of `buf`, either an asset or a bare manifest store
+pub fn summarize(buf: &[u8]) -> Option<ManifestSummary> {
+    if jumbf::is_c2pa_store(buf) {
+        summarize_store(buf)
+    } else {
+        summarize_store(&extract_store(buf)?.bytes)
+    }
+}
+
+/// Summarizes the active manifest of a JUMBF manifest store
+pub fn summarize_store(store: &[u8]) -> Option<ManifestSummary> {
+    let tree = parse_tree(store);
+    let root = tree.boxes.first()?;
+    // The active manifest is the last one in the store
+    let manifest = root
+        .children
+        .iter()
+        .rev()
+        .find(|child| matches!(child.content_type(), Some("c2ma") | Some("c2um")))?;
+
+    let claim = manifest
+        .children
+        .iter()
+        .find(|child| {
+            child
+                .label()
+                .is_some_and(|label| label.starts_with("c2pa.claim"))
+        })
+        .and_then(|claim| cbor_content(store, claim));
+    let signature = manifest
+        .child(SIGNATURE_LABEL)
+        .and_then(|signature| cbor_content(store, signature));
+    let text = |key: &str| {
+        claim
+            .as_ref()?
+            .get(key)
+            .and_then(Value::as_text)
+            .map(str::to_string)
+    };
+
+    Some(ManifestSummary {
+        verified: false,
+        active_manifest: manifest.label()?.to_string(),
+        claim_generator: text("claim_generator").or_else(|| generator_info(claim.as_ref()?)),
+        title: text("dc:title"),
+        format: text("dc:format"),
+        issuer: signature.as_ref().and_then(issuer),
+        signing_time: signature.as_ref().and_then(signing_time),
+    })
+}
+
+fn cbor_content(store: &[u8], superbox: &JumbfBox) -> Option<Value> {
+    cbor::decode(superbox.content_box("cbor")?.content(store))
+}
+
+/// Names the generator from `claim_generator_info`, used by v2 claims
+fn generator_info(claim: &Value) -> Option<String> {
+    let info = claim.get("claim_generator_info")?;
+    let info = match info.as_array() {
+        Some(items) => items.first()?,
+        None => info,
+    };
+    let name = info.get("name")?.as_text()?;
+    match info.get("version").and_then(Value::as_text) {
+        Some(version) => Some(format!("{}/{}", name, version)),
+        None => Some(name.to_string()),
+    }
+}
+
+/// Looks up a COSE_Sign1 header parameter, protected headers first
+fn header<'a>(
+    signature: &'a Value,
+    protected: &'a Option<Value>,
+    find: impl Fn(&'a Value) -> Option<&'a Value>,
+) -> Option<&'a Value> {
+    let unprotected = signature.untagged().as_array()?.get(1);
+    protected
+        .as_ref()
+        .and_then(&find)
+        .or_else(|| find(unprotected?))
+}
+
+fn protected_headers(signature: &Value) -> Option<Value> {
+    let bytes = signature.untagged().as_array()?.first()?.as_bytes()?;
+    cbor::decode(bytes)
+}
+
+fn issuer(signature: &Value) -> Option<String> {
+    let protected = protected_headers(signature);
+    // Early signers wrote the chain under a text label
+    let chain = header(signature, &protected, |headers| {
+        headers.get_int(X5CHAIN).or_else(|| headers.get("x5chain"))
+    })?;
+    let leaf = match chain.as_array() {
+        Some(certs) => certs.first()?.as_bytes()?,
+        None => chain.as_bytes()?,
+    };
+    issuer_common_name(leaf)
+}
+
+/// Reads the CN attribute of the issuer name of a DER encoded X.509 certificate
+fn issuer_common_name(cert: &[u8]) -> Option<String> {
+    let (cert, _) = der::read(cert)?;
+    let tbs = der::children(cert.content).next()?;
+    // The version is optional, then come the serial number and signature algorithm
+    let mut fields = der::children(tbs.content).skip_while(|field| field.tag == der::CONTEXT_0);
+    let issuer = fields.nth(2)?;
+
+    der::children(issuer.content)
+        .flat_map(|rdn| der::children(rdn.content))
+        .find_map(|attribute| {
+            let mut parts = der::children(attribute.content);
+            if parts.next()?.content != COMMON_NAME_OID {
+                return None;
+            }
+            Some(String::from_utf8_lossy(parts.next()?.content).into_owned())
+        })
+}
+
+/// Reads `genTime` from the first RFC 3161 time-stamp token of the signature
+fn signing_time(signature: &Value) -> Option<String> {
+    let protected = protected_headers(signature);
+    let timestamps = header(signature, &protected, |headers| {
+        headers.get("sigTst2").or_else(|| headers.get("sigTst"))
+    })?;
+    let token = timestamps
+        .get("tstTokens")?
+        .as_array()?
+        .first()?
+        .get("val")?
+        .as_bytes()?;
+    // `TSTInfo` is the first content of the token to hold a GeneralizedTime
+    let time = der::find(token, der::GENERALIZED_TIME)?;
+    iso_time(std::str::from_utf8(time.content).ok()?)
+}
+
+/// Turns a `YYYYMMDDHHMMSS[.fff]Z` GeneralizedTime into ISO 8601
+fn iso_time(time: &str) -> Option<String> {
+    let digits = time.get(..14)?;
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(format!(
+        "{}-{}-{}T{}:{}:{}{}",
+        &digits[..4],
+        &digits[4..6],
+        &digits[6..8],
+        &digits[8..10],
+        &digits[10..12],
+        &digits[12..14],
+        &time[14..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_active_manifest() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+
+        let summary = summarize(asset).unwrap();
+        assert!(!summary.verified);
+        assert_eq!(
+            summary.active_manifest,
+            "adobetest:urn:uuid:825cf3cf-0127-4af3-b65c-c11d0f961e67"
+        );
+        assert_eq!(summary.claim_generator.as_deref(), Some("C2PA Testing"));
+        assert_eq!(summary.title.as_deref(), Some("CAICAI.jpg"));
+        assert_eq!(summary.format.as_deref(), Some("image/jpeg"));
+        assert_eq!(summary.issuer.as_deref(), Some("contentauthenticity.org"));
+        assert_eq!(
+            summary.signing_time.as_deref(),
+            Some("2022-04-20T22:44:41Z")
+        );
+    }
+
+    #[test]
+    fn formats_generalized_time() {
+        assert_eq!(
+            iso_time("20230102030405Z").as_deref(),
+            Some("2023-01-02T03:04:05Z")
+        );
+        assert_eq!(iso_time("2023"), None);
+    }
+}
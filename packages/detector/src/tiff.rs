@@ -0,0 +1,494 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! TIFF (and TIFF based raw formats such as DNG) IFD walker that locates the
+//! C2PA tag pointing to the Manifest Store.
+//!
+//! Both byte orders are supported, as is BigTIFF with its 64-bit offsets.
+
+use crate::detection::{Findings, Location};
+use crate::jumbf;
+use crate::plan::ByteRange;
+use std::convert::TryFrom;
+
+/// Tag whose value is the C2PA Manifest Store
+pub const C2PA_TAG: u16 = 0xCD41;
+const XMP_TAG: u16 = 0x02BC;
+
+const CLASSIC_VERSION: u16 = 42;
+const BIG_TIFF_VERSION: u16 = 43;
+
+/// IFDs followed before giving up on a chain
+const MAX_IFDS: usize = 64;
+
+/// Entries read from a single IFD, the most a classic TIFF can hold
+const MAX_ENTRIES: u64 = u16::MAX as u64;
+
+/// XMP values larger than this are not inspected
+const MAX_XMP_LEN: u64 = 1024 * 1024;
+
+/// Leading bytes of a C2PA tag value read to check that it holds a store
+const STORE_PEEK_LEN: u64 = 256;
+
+pub fn is_tiff(buf: &[u8]) -> bool {
+    header(buf).is_some()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+/// Byte order and offset size of the file, from its header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    order: ByteOrder,
+    big_tiff: bool,
+}
+
+impl Layout {
+    fn header_len(&self) -> usize {
+        if self.big_tiff {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Size of the entry count and the next IFD offset
+    fn count_len(&self) -> usize {
+        if self.big_tiff {
+            8
+        } else {
+            2
+        }
+    }
+
+    fn offset_len(&self) -> usize {
+        if self.big_tiff {
+            8
+        } else {
+            4
+        }
+    }
+
+    fn entry_len(&self) -> usize {
+        if self.big_tiff {
+            20
+        } else {
+            12
+        }
+    }
+
+    /// Reads an unsigned integer of 2, 4 or 8 bytes
+    fn read(&self, bytes: &[u8]) -> u64 {
+        let mut value = 0u64;
+        match self.order {
+            ByteOrder::Big => {
+                for &b in bytes {
+                    value = value << 8 | b as u64;
+                }
+            }
+            ByteOrder::Little => {
+                for &b in bytes.iter().rev() {
+                    value = value << 8 | b as u64;
+                }
+            }
+        }
+        value
+    }
+}
+
+fn header(buf: &[u8]) -> Option<Layout> {
+    let order = match buf.get(..2)? {
+        b"II" => ByteOrder::Little,
+        b"MM" => ByteOrder::Big,
+        _ => return None,
+    };
+    let layout = |big_tiff| Layout { order, big_tiff };
+    match layout(false).read(buf.get(2..4)?) as u16 {
+        CLASSIC_VERSION => Some(layout(false)),
+        BIG_TIFF_VERSION => Some(layout(true)),
+        _ => None,
+    }
+}
+
+/// Whether the first IFD, if it lies within `buf`, has an entry for `tag`
+pub fn first_ifd_has_tag(buf: &[u8], tag: u16) -> bool {
+    let layout = match header(buf) {
+        Some(layout) => layout,
+        None => return false,
+    };
+    let offset_field = layout.header_len() - layout.offset_len()..layout.header_len();
+    let ifd = match buf
+        .get(offset_field)
+        .map(|offset| layout.read(offset) as usize)
+    {
+        Some(offset) => buf.get(offset..).unwrap_or_default(),
+        None => return false,
+    };
+    let count = match ifd.get(..layout.count_len()) {
+        Some(count) => layout.read(count).min(MAX_ENTRIES) as usize,
+        None => return false,
+    };
+    ifd[layout.count_len()..]
+        .chunks_exact(layout.entry_len())
+        .take(count)
+        .any(|entry| layout.read(&entry[..2]) as u16 == tag)
+}
+
+/// Returns the manifest store held by the C2PA tag value at `location`
+pub fn store_data(buf: &[u8], location: Location) -> Option<&[u8]> {
+    let end = location.offset.checked_add(location.length?)?;
+    buf.get(location.offset..end)
+}
+
+/// Size in bytes of a single value of the given field type
+fn type_size(field_type: u16) -> u64 {
+    match field_type {
+        // SHORT, SSHORT
+        3 | 8 => 2,
+        // LONG, SLONG, FLOAT, IFD
+        4 | 9 | 11 | 13 => 4,
+        // RATIONAL, SRATIONAL, DOUBLE, LONG8, SLONG8, IFD8
+        5 | 10 | 12 | 16 | 17 | 18 => 8,
+        // BYTE, ASCII, SBYTE, UNDEFINED and unknown types
+        _ => 1,
+    }
+}
+
+/// Structures still to be read, by absolute offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Ifd(u64),
+    Store { offset: u64, length: u64 },
+    Xmp { offset: u64, length: u64 },
+}
+
+impl Target {
+    fn offset(&self) -> u64 {
+        match *self {
+            Target::Ifd(offset) | Target::Store { offset, .. } | Target::Xmp { offset, .. } => {
+                offset
+            }
+        }
+    }
+}
+
+/// Resumable walk over the IFD chain
+///
+/// IFDs and values are read in file order, but an IFD may point back to values
+/// stored before it, so nothing is dropped until the walk is over.
+#[derive(Debug, Default)]
+pub struct TiffScanner {
+    /// The store location is the offset and length of the C2PA tag value
+    pub findings: Findings,
+    layout: Option<Layout>,
+    targets: Vec<Target>,
+    /// Offsets of the IFDs queued so far, so that a looping chain is not followed
+    ifds: Vec<u64>,
+    finished: bool,
+}
+
+impl TiffScanner {
+    /// Reads the IFDs in `buf`, located at absolute offset `base`, and returns
+    /// how many bytes can be dropped: none until the walk is over
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        let layout = match self.layout {
+            Some(layout) => layout,
+            None => {
+                let layout = match header(buf) {
+                    Some(layout) if buf.len() >= layout.header_len() => layout,
+                    _ if eof => {
+                        self.finished = true;
+                        return buf.len();
+                    }
+                    _ => return 0,
+                };
+                let first_ifd = layout
+                    .read(&buf[layout.header_len() - layout.offset_len()..][..layout.offset_len()]);
+                self.layout = Some(layout);
+                self.push_ifd(first_ifd);
+                layout
+            }
+        };
+
+        while !self.finished {
+            let target = match self.next_target() {
+                Some(target) => target,
+                None => {
+                    self.finished = true;
+                    break;
+                }
+            };
+            let rel = match usize::try_from(target.offset()) {
+                Ok(offset) if offset >= base => offset - base,
+                _ => {
+                    self.targets.retain(|&t| t != target);
+                    continue;
+                }
+            };
+            let available = buf.get(rel..).unwrap_or_default();
+
+            let needed = match target {
+                Target::Ifd(_) => match available.get(..layout.count_len()) {
+                    Some(count) => (layout.read(count).min(MAX_ENTRIES) as usize)
+                        .saturating_mul(layout.entry_len())
+                        .saturating_add(layout.count_len() + layout.offset_len()),
+                    None => layout.count_len(),
+                },
+                Target::Store { length, .. } => length.min(STORE_PEEK_LEN) as usize,
+                Target::Xmp { length, .. } => length as usize,
+            };
+            if available.len() < needed && !eof {
+                return 0;
+            }
+
+            self.targets.retain(|&t| t != target);
+            match target {
+                Target::Ifd(_) => self.read_ifd(&layout, available, target.offset()),
+                Target::Store { offset, length } => {
+                    if jumbf::is_c2pa_store(&available[..available.len().min(needed)]) {
+                        self.findings.add_store(Location {
+                            offset: offset as usize,
+                            length: Some(length as usize),
+                        });
+                        self.finished = self.findings.is_settled();
+                    }
+                }
+                Target::Xmp { offset, .. } => {
+                    let data = &available[..available.len().min(needed)];
+                    self.findings.add_xmp(data, offset as usize);
+                }
+            }
+        }
+        buf.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Range of the first C2PA tag value found in the IFDs read so far but not
+    /// yet reached
+    pub fn pending_store(&self) -> Option<ByteRange> {
+        self.targets
+            .iter()
+            .filter_map(|target| match *target {
+                Target::Store { offset, length } => Some(ByteRange::new(
+                    usize::try_from(offset).ok()?,
+                    usize::try_from(length).unwrap_or(usize::MAX),
+                )),
+                _ => None,
+            })
+            .min_by_key(|range| range.offset)
+    }
+
+    /// Ranges of the IFDs not yet reached, each as long as its largest possible
+    /// size, once the header has been read
+    pub fn pending_ifds(&self) -> Option<Vec<ByteRange>> {
+        let layout = self.layout?;
+        let max_len =
+            layout.count_len() + MAX_ENTRIES as usize * layout.entry_len() + layout.offset_len();
+        let mut ranges: Vec<ByteRange> = self
+            .targets
+            .iter()
+            .filter_map(|target| match *target {
+                Target::Ifd(offset) => Some(ByteRange::new(usize::try_from(offset).ok()?, max_len)),
+                _ => None,
+            })
+            .collect();
+        ranges.sort_by_key(|range| range.offset);
+        Some(ranges)
+    }
+
+    /// Returns the closest target
+    fn next_target(&self) -> Option<Target> {
+        self.targets.iter().copied().min_by_key(Target::offset)
+    }
+
+    fn push_ifd(&mut self, offset: u64) {
+        if offset != 0 && self.ifds.len() < MAX_IFDS && !self.ifds.contains(&offset) {
+            self.ifds.push(offset);
+            self.targets.push(Target::Ifd(offset));
+        }
+    }
+
+    /// Reads the IFD at the start of `data`, which may be truncated at the end of the file
+    fn read_ifd(&mut self, layout: &Layout, data: &[u8], offset: u64) {
+        let count_len = layout.count_len();
+        let offset_len = layout.offset_len();
+        let count = match data.get(..count_len) {
+            Some(count) => layout.read(count).min(MAX_ENTRIES),
+            None => return,
+        };
+
+        let entries = data[count_len..].chunks_exact(layout.entry_len());
+        for (i, entry) in entries.take(count as usize).enumerate() {
+            let tag = layout.read(&entry[..2]) as u16;
+            if tag != C2PA_TAG && tag != XMP_TAG {
+                continue;
+            }
+            let field_type = layout.read(&entry[2..4]) as u16;
+            let value_count = layout.read(&entry[4..4 + offset_len]);
+            let length = value_count.saturating_mul(type_size(field_type));
+            let value_field = &entry[4 + offset_len..];
+            // Values that fit in the entry are stored inline
+            let value_offset = if length <= offset_len as u64 {
+                offset + (count_len + i * layout.entry_len() + 4 + offset_len) as u64
+            } else {
+                layout.read(value_field)
+            };
+
+            if tag == C2PA_TAG {
+                self.targets.push(Target::Store {
+                    offset: value_offset,
+                    length,
+                });
+            } else if self.findings.wants_provenance() && length <= MAX_XMP_LEN {
+                self.targets.push(Target::Xmp {
+                    offset: value_offset,
+                    length,
+                });
+            }
+        }
+
+        let next_start =
+            count_len.saturating_add((count as usize).saturating_mul(layout.entry_len()));
+        if let Some(next) = data.get(next_start..next_start + offset_len) {
+            self.push_ifd(layout.read(next));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::{detect, DetectionResult};
+    use crate::extract::extract_store;
+    use crate::stream::StreamDetector;
+
+    fn manifest_store() -> Vec<u8> {
+        let jpeg = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        extract_store(jpeg).unwrap().bytes
+    }
+
+    /// Builds a file with an empty first IFD linking to a second IFD whose C2PA tag holds `store`
+    fn asset(order: ByteOrder, big_tiff: bool, store: &[u8]) -> Vec<u8> {
+        let layout = Layout { order, big_tiff };
+        let int = |value: u64, len: usize| -> Vec<u8> {
+            let bytes = value.to_be_bytes();
+            let mut int = bytes[8 - len..].to_vec();
+            if order == ByteOrder::Little {
+                int.reverse();
+            }
+            int
+        };
+        let offset_len = layout.offset_len();
+
+        let mut asset = match order {
+            ByteOrder::Little => b"II".to_vec(),
+            ByteOrder::Big => b"MM".to_vec(),
+        };
+        if big_tiff {
+            asset.extend(int(43, 2));
+            asset.extend(int(8, 2));
+            asset.extend(int(0, 2));
+        } else {
+            asset.extend(int(42, 2));
+        }
+        let first_ifd = layout.header_len() + 1000;
+        asset.extend(int(first_ifd as u64, offset_len));
+        // Stand-in for image data
+        asset.resize(first_ifd, 0xAA);
+
+        let second_ifd = first_ifd + layout.count_len() + offset_len;
+        asset.extend(int(0, layout.count_len()));
+        asset.extend(int(second_ifd as u64, offset_len));
+
+        let store_offset = second_ifd + layout.count_len() + layout.entry_len() + offset_len;
+        asset.extend(int(1, layout.count_len()));
+        asset.extend(int(C2PA_TAG as u64, 2));
+        asset.extend(int(7, 2));
+        asset.extend(int(store.len() as u64, offset_len));
+        asset.extend(int(store_offset as u64, offset_len));
+        asset.extend(int(0, offset_len));
+        asset.extend_from_slice(store);
+        asset
+    }
+
+    fn store(asset: &[u8]) -> Option<Location> {
+        let mut scanner = TiffScanner::default();
+        scanner.scan(asset, 0, true);
+        scanner.findings.store()
+    }
+
+    #[test]
+    fn finds_tag_in_both_byte_orders() {
+        let manifest_store = manifest_store();
+        for &order in &[ByteOrder::Little, ByteOrder::Big] {
+            let asset = asset(order, false, &manifest_store);
+            let store = store(&asset).unwrap();
+
+            assert_eq!(store_data(&asset, store), Some(&manifest_store[..]));
+        }
+    }
+
+    #[test]
+    fn finds_tag_in_big_tiff() {
+        let manifest_store = manifest_store();
+        let asset = asset(ByteOrder::Big, true, &manifest_store);
+        let store = store(&asset).unwrap();
+
+        assert_eq!(store_data(&asset, store), Some(&manifest_store[..]));
+    }
+
+    #[test]
+    fn ignores_tag_without_store() {
+        let asset = asset(ByteOrder::Little, false, b"\0\0\0\x28jumb\0\0\0\x20jumd");
+
+        assert_eq!(store(&asset), None);
+    }
+
+    #[test]
+    fn streams_xmp_stored_before_its_ifd() {
+        let xmp = br#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF><rdf:Description dcterms:provenance="https://example.com/a.c2pa"/></rdf:RDF></x:xmpmeta>"#;
+        let ifd = 8 + xmp.len() + 1000;
+        let mut asset = b"II\x2a\0".to_vec();
+        asset.extend_from_slice(&(ifd as u32).to_le_bytes());
+        asset.extend_from_slice(xmp);
+        // Stand-in for image data
+        asset.resize(ifd, 0xAA);
+        asset.extend_from_slice(&1u16.to_le_bytes());
+        asset.extend_from_slice(&XMP_TAG.to_le_bytes());
+        asset.extend_from_slice(&1u16.to_le_bytes());
+        asset.extend_from_slice(&(xmp.len() as u32).to_le_bytes());
+        asset.extend_from_slice(&8u32.to_le_bytes());
+        asset.extend_from_slice(&0u32.to_le_bytes());
+
+        let whole = detect(&asset);
+        assert!(matches!(whole, DetectionResult::RemoteReference { .. }));
+        for &chunk_len in &[1, 64] {
+            let mut detector = StreamDetector::new();
+            for chunk in asset.chunks(chunk_len) {
+                if detector.push(chunk) {
+                    break;
+                }
+            }
+            assert_eq!(detector.finish(), whole, "chunks of {}", chunk_len);
+        }
+    }
+
+    #[test]
+    fn stops_on_looping_chain() {
+        let mut asset = b"II\x2a\0\x08\0\0\0".to_vec();
+        asset.extend_from_slice(&[0, 0, 8, 0, 0, 0]);
+
+        assert_eq!(store(&asset), None);
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Resolution of URI references against a base URL, following RFC 3986.
+//!
+//! Components are kept as written: nothing is percent-encoded, decoded or
+//! lowercased beyond what resolution requires.
+
+/// The components of a URI reference
+struct Reference<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+fn is_scheme(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+fn split_off(s: &str, delimiter: char) -> (&str, Option<&str>) {
+    match s.find(delimiter) {
+        Some(pos) => (&s[..pos], Some(&s[pos + 1..])),
+        None => (s, None),
+    }
+}
+
+fn parse(s: &str) -> Reference<'_> {
+    let (s, fragment) = split_off(s, '#');
+    let (s, query) = split_off(s, '?');
+    let (scheme, s) = match s.find(':').filter(|&pos| is_scheme(&s[..pos])) {
+        Some(pos) => (Some(&s[..pos]), &s[pos + 1..]),
+        None => (None, s),
+    };
+    let (authority, path) = match s.strip_prefix("//") {
+        Some(rest) => {
+            let end = rest.find('/').unwrap_or(rest.len());
+            (Some(&rest[..end]), &rest[end..])
+        }
+        None => (None, s),
+    };
+    Reference {
+        scheme,
+        authority,
+        path,
+        query,
+        fragment,
+    }
+}
+
+/// Removes the `.` and `..` segments of `path` (RFC 3986, section 5.2.4)
+fn remove_dot_segments(path: &str) -> String {
+    let mut output: Vec<&str> = Vec::new();
+    let mut input = path;
+    while !input.is_empty() {
+        if let Some(rest) = input
+            .strip_prefix("../")
+            .or_else(|| input.strip_prefix("./"))
+        {
+            input = rest;
+        } else if input.starts_with("/./") {
+            input = &input[2..];
+        } else if input == "/." {
+            input = "/";
+        } else if input.starts_with("/../") {
+            input = &input[3..];
+            output.pop();
+        } else if input == "/.." {
+            input = "/";
+            output.pop();
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            let start = usize::from(input.starts_with('/'));
+            let end = input[start..]
+                .find('/')
+                .map_or(input.len(), |pos| start + pos);
+            output.push(&input[..end]);
+            input = &input[end..];
+        }
+    }
+    output.concat()
+}
+
+/// Joins a relative path to the directory of the base path (RFC 3986, section 5.2.3)
+fn merge(base: &Reference, path: &str) -> String {
+    if base.authority.is_some() && base.path.is_empty() {
+        return format!("/{}", path);
+    }
+    let directory = base.path.rfind('/').map_or("", |pos| &base.path[..=pos]);
+    format!("{}{}", directory, path)
+}
+
+/// Resolves `reference` against `base`, returning `None` when `reference` is
+/// relative and `base` is not an absolute URL
+///
+/// Surrounding whitespace, and tabs and newlines within the reference, are
+/// dropped as browsers do.
+pub fn resolve(base: &str, reference: &str) -> Option<String> {
+    let reference: String = reference
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    let reference = parse(&reference);
+    let base = parse(base.trim());
+
+    let (scheme, authority, path, query) = if let Some(scheme) = reference.scheme {
+        let path = remove_dot_segments(reference.path);
+        (scheme, reference.authority, path, reference.query)
+    } else {
+        let scheme = base.scheme?;
+        if reference.authority.is_some() {
+            let path = remove_dot_segments(reference.path);
+            (scheme, reference.authority, path, reference.query)
+        } else if reference.path.is_empty() {
+            let query = reference.query.or(base.query);
+            (scheme, base.authority, base.path.to_string(), query)
+        } else if reference.path.starts_with('/') {
+            let path = remove_dot_segments(reference.path);
+            (scheme, base.authority, path, reference.query)
+        } else {
+            let path = remove_dot_segments(&merge(&base, reference.path));
+            (scheme, base.authority, path, reference.query)
+        }
+    };
+
+    let mut url = format!("{}:", scheme);
+    if let Some(authority) = authority {
+        url.push_str("//");
+        url.push_str(authority);
+    }
+    url.push_str(&path);
+    if let Some(query) = query {
+        url.push('?');
+        url.push_str(query);
+    }
+    if let Some(fragment) = reference.fragment {
+        url.push('#');
+        url.push_str(fragment);
+    }
+    Some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_rfc_examples() {
+        let base = "http://a/b/c/d;p?q";
+        let examples = [
+            ("g:h", "g:h"),
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"),
+            ("g#s", "http://a/b/c/g#s"),
+            ("", "http://a/b/c/d;p?q"),
+            (".", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../../g", "http://a/g"),
+            ("../../../g", "http://a/g"),
+            ("/./g", "http://a/g"),
+            ("g.", "http://a/b/c/g."),
+            ("./../g", "http://a/b/g"),
+            ("g/../h", "http://a/b/c/h"),
+            ("g;x=1/../y", "http://a/b/c/y"),
+            ("g#s/../x", "http://a/b/c/g#s/../x"),
+        ];
+
+        for (reference, expected) in &examples {
+            assert_eq!(resolve(base, reference).as_deref(), Some(*expected));
+        }
+    }
+
+    #[test]
+    fn needs_absolute_base_for_relative_references() {
+        assert_eq!(resolve("/page.html", "manifest.c2pa"), None);
+        assert_eq!(
+            resolve("", " https://cdn.example/m.c2pa\n"),
+            Some("https://cdn.example/m.c2pa".to_string())
+        );
+        assert_eq!(
+            resolve("https://example.com", "m.c2pa"),
+            Some("https://example.com/m.c2pa".to_string())
+        );
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! JavaScript bindings, built with `wasm-pack`.
+//!
+//! Inputs are read through `input` and results are handed back as plain objects
+//! matching the TypeScript declarations below.
+
+use crate::stream::StreamDetector;
+use crate::{detection, extract, input, inspect, links, plan, sniff, summary};
+use std::panic;
+use wasm_bindgen::prelude::*;
+
+/// Leading bytes `sniff_format` looks at, enough for every format but a DNG whose
+/// first IFD lies further in
+const SNIFF_LEN: usize = 64 * 1024;
+
+#[wasm_bindgen(typescript_custom_section)]
+pub const TS_APPEND_CONTENT: &str = r#"
+export type Format = 'jpeg' | 'png' | 'bmff' | 'riff' | 'pdf' | 'tiff' | 'gif' | 'jxl' | 'mp3' | 'flac' | 'xml' | 'zip' | 'unknown';
+
+export type Damage =
+    | { type: 'checksumMismatch'; expected: number; actual: number }
+    | { type: 'missingPadding' }
+    | { type: 'truncated'; container: string; expected: number; available: number }
+    | { type: 'missingSegment'; sequence: number; expected: number; available: number };
+
+export type DetectionResult =
+    | { kind: 'manifestStore'; offset: number; length?: number; format: Format }
+    | { kind: 'damaged'; offset: number; format: Format; damage: Damage }
+    | { kind: 'remoteReference'; offset: number; format: Format; url?: string }
+    | { kind: 'notPresent'; format: Format }
+    | { kind: 'noContainer'; format: Format };
+
+export type Occurrence =
+    | { kind: 'manifestStore'; offset: number; length?: number }
+    | { kind: 'damaged'; offset: number; damage: Damage }
+    | { kind: 'remoteReference'; offset: number; url?: string };
+
+export interface ScanReport {
+    format: Format;
+    occurrences: Occurrence[];
+    multipleStores: boolean;
+}
+
+export interface ByteRange {
+    offset: number;
+    length: number;
+}
+
+export type RangePlan =
+    | { kind: 'located'; format: Format; ranges: ByteRange[] }
+    | { kind: 'candidates'; format: Format; ranges: ByteRange[] }
+    | { kind: 'notPresent'; format: Format };
+
+export interface ExtractedStore {
+    format: Format;
+    bytes: Uint8Array;
+}
+
+export type JumbfIssue =
+    | { type: 'truncated'; declared: number; available: number }
+    | { type: 'invalidHeader'; offset: number; length: number }
+    | { type: 'missingDescription' }
+    | { type: 'invalidDescription' }
+    | { type: 'tooDeep' };
+
+export interface JumbfDescription {
+    contentType: string;
+    toggles: number;
+    requestable: boolean;
+    label?: string;
+    id?: number;
+    hasSignature: boolean;
+    hasPrivate: boolean;
+}
+
+export interface JumbfBox {
+    offset: number;
+    type: string;
+    length: number;
+    description?: JumbfDescription;
+    children?: JumbfBox[];
+    issues?: JumbfIssue[];
+}
+
+export interface JumbfTree {
+    boxes: JumbfBox[];
+    issues?: JumbfIssue[];
+}
+
+export interface ManifestSummary {
+    verified: false;
+    activeManifest: string;
+    claimGenerator?: string;
+    title?: string;
+    format?: string;
+    issuer?: string;
+    signingTime?: string;
+}
+
+export type AssetBytes = ArrayBuffer | SharedArrayBuffer | ArrayBufferView;
+
+export function scan_array_buffer(buf: AssetBytes): DetectionResult;
+export function scan_head_array_buffer(head: AssetBytes): DetectionResult;
+export function scan_all_array_buffer(buf: AssetBytes): ScanReport;
+export function sniff_format(buf: AssetBytes): string | undefined;
+export function plan_ranges(head: AssetBytes, totalSize: number): RangePlan;
+export function extract_manifest_store(buf: AssetBytes): ExtractedStore | undefined;
+export function inspect_jumbf(buf: AssetBytes): JumbfTree;
+export function summarize_manifest(buf: AssetBytes): ManifestSummary | undefined;
+export function html_manifest_links(html: string, baseUrl: string): string[];
+export function link_header_manifest_links(value: string, baseUrl: string): string[];
+"#;
+
+#[wasm_bindgen(start)]
+pub fn main() {
+    panic::set_hook(Box::new(console_error_panic_hook::hook));
+}
+
+/// Scans an asset for C2PA metadata, returning a `DetectionResult`
+#[wasm_bindgen(skip_typescript)]
+pub fn scan_array_buffer(buf: JsValue) -> Result<JsValue, JsValue> {
+    let result = input::detect(&buf)?;
+
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// Scans the first bytes of an asset for C2PA metadata, returning a
+/// `DetectionResult` in which a store running past them is not truncated
+#[wasm_bindgen(skip_typescript)]
+pub fn scan_head_array_buffer(head: JsValue) -> Result<JsValue, JsValue> {
+    let head = input::to_vec(&head)?;
+    let result = detection::detect_head(&head);
+
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+/// Scans the whole asset, returning a `ScanReport` of every store and provenance
+/// reference found
+#[wasm_bindgen(skip_typescript)]
+pub fn scan_all_array_buffer(buf: JsValue) -> Result<JsValue, JsValue> {
+    let report = input::scan_all(&buf)?;
+
+    Ok(serde_wasm_bindgen::to_value(&report)?)
+}
+
+/// Identifies the asset's MIME type from its leading bytes, returning `undefined`
+/// for unrecognised formats
+#[wasm_bindgen(skip_typescript)]
+pub fn sniff_format(buf: JsValue) -> Result<Option<String>, JsValue> {
+    let scan_bytes = input::prefix_to_vec(&buf, SNIFF_LEN)?;
+
+    Ok(sniff::sniff_format(&scan_bytes).map(String::from))
+}
+
+/// Plans the byte ranges holding the manifest store of an asset of `total_size`
+/// bytes from its first bytes, returning a `RangePlan`
+#[wasm_bindgen(skip_typescript)]
+pub fn plan_ranges(head: JsValue, total_size: f64) -> Result<JsValue, JsValue> {
+    let head = input::to_vec(&head)?;
+    let plan = plan::plan_ranges(&head, total_size as usize);
+
+    Ok(serde_wasm_bindgen::to_value(&plan)?)
+}
+
+/// Returns the raw, unverified manifest store embedded in the asset as an
+/// `ExtractedStore`, or `undefined` if there is none that can be read as-is
+#[wasm_bindgen(skip_typescript)]
+pub fn extract_manifest_store(buf: JsValue) -> Result<JsValue, JsValue> {
+    let scan_bytes = input::to_vec(&buf)?;
+    let store = extract::extract_store(&scan_bytes);
+
+    Ok(serde_wasm_bindgen::to_value(&store)?)
+}
+
+/// Parses JUMBF data, such as an extracted manifest store, returning its box tree
+/// as a `JumbfTree` with malformed or truncated boxes marked
+#[wasm_bindgen(skip_typescript)]
+pub fn inspect_jumbf(buf: JsValue) -> Result<JsValue, JsValue> {
+    let jumbf = input::to_vec(&buf)?;
+    let tree = inspect::parse_tree(&jumbf);
+
+    Ok(serde_wasm_bindgen::to_value(&tree)?)
+}
+
+/// Summarizes the active manifest of an asset or bare manifest store as a
+/// `ManifestSummary`, without validating anything
+#[wasm_bindgen(skip_typescript)]
+pub fn summarize_manifest(buf: JsValue) -> Result<JsValue, JsValue> {
+    let scan_bytes = input::to_vec(&buf)?;
+    let summary = summary::summarize(&scan_bytes);
+
+    Ok(serde_wasm_bindgen::to_value(&summary)?)
+}
+
+/// Returns the manifest store URLs linked from the head of an HTML document with
+/// `<link rel="c2pa-manifest">`, resolved against the document URL `base_url`
+#[wasm_bindgen(skip_typescript)]
+pub fn html_manifest_links(html: &str, base_url: &str) -> Result<JsValue, JsValue> {
+    let urls = links::html_manifest_links(html, base_url);
+
+    Ok(serde_wasm_bindgen::to_value(&urls)?)
+}
+
+/// Returns the manifest store URLs with the `c2pa-manifest` relation in an HTTP
+/// `Link` header value, resolved against the response URL `base_url`
+#[wasm_bindgen(skip_typescript)]
+pub fn link_header_manifest_links(value: &str, base_url: &str) -> Result<JsValue, JsValue> {
+    let urls = links::link_header_manifest_links(value, base_url);
+
+    Ok(serde_wasm_bindgen::to_value(&urls)?)
+}
+
+/// Incremental detector for assets that arrive in chunks, e.g. from a `fetch` body stream
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Detector {
+    inner: StreamDetector,
+}
+
+#[wasm_bindgen]
+impl Detector {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Detector {
+        Detector::default()
+    }
+
+    /// Scans the next chunk, returning `true` once no more input is needed
+    pub fn push(&mut self, chunk: JsValue) -> Result<bool, JsValue> {
+        input::push(&mut self.inner, &chunk)
+    }
+
+    /// Returns the `DetectionResult` for everything pushed so far
+    pub fn finish(&mut self) -> Result<JsValue, JsValue> {
+        let result = self.inner.finish();
+
+        Ok(serde_wasm_bindgen::to_value(&result)?)
+    }
+}
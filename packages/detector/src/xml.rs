@@ -0,0 +1,436 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! XML tokenizer that locates the `<c2pa:manifest>` element of SVG and other
+//! XML-based assets.
+//!
+//! The element holds the C2PA Manifest Store as base64 text, so the store UUID
+//! never appears in the raw bytes. Only enough of XML is understood to tell
+//! elements apart from comments, CDATA sections, processing instructions and
+//! quoted attribute values.
+
+use crate::detection::{Findings, Location};
+use crate::jumbf;
+use twoway::find_bytes;
+
+const MANIFEST: &[u8] = b"c2pa:manifest";
+/// Elements whose content is handed to the XMP search
+const XMP_ELEMENTS: [&[u8]; 2] = [b"x:xmpmeta", b"rdf:RDF"];
+
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+const CDATA_START: &[u8] = b"<![CDATA[";
+const CDATA_END: &[u8] = b"]]>";
+
+/// Bytes needed after a `<` to tell what kind of markup it starts and read an
+/// element name
+const LOOKAHEAD: usize = 64;
+
+/// Manifest elements larger than this are not inspected
+const MAX_MANIFEST_LEN: usize = 64 * 1024 * 1024;
+/// XMP elements larger than this are not inspected
+const MAX_XMP_LEN: usize = 1024 * 1024;
+
+/// Whether `buf` starts with markup, possibly after a byte order mark and whitespace
+pub fn is_xml(buf: &[u8]) -> bool {
+    let text = buf.strip_prefix(BOM).unwrap_or(buf);
+    text.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'<')
+}
+
+fn is_name_end(b: u8) -> bool {
+    b.is_ascii_whitespace() || b == b'/' || b == b'>'
+}
+
+/// Returns the offset of the `>` ending the tag that starts `buf`, skipping
+/// over quoted attribute values
+fn tag_end(buf: &[u8]) -> Option<usize> {
+    let mut quote = None;
+    for (i, &b) in buf.iter().enumerate() {
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(_) => {}
+            None if b == b'"' || b == b'\'' => quote = Some(b),
+            None if b == b'>' => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Returns the content of the element that starts `element`, which runs to the
+/// end of `element` when its end tag is missing
+fn element_content<'a>(element: &'a [u8], name: &[u8]) -> Option<&'a [u8]> {
+    let start = tag_end(element)? + 1;
+    let content = &element[start..];
+    let end = find_end_tag(content, name).unwrap_or(content.len());
+    Some(&content[..end])
+}
+
+fn find_end_tag(buf: &[u8], name: &[u8]) -> Option<usize> {
+    let mut from = 0;
+    while let Some(pos) = find_bytes(&buf[from..], b"</") {
+        let at = from + pos;
+        let rest = &buf[at + 2..];
+        if rest.starts_with(name) && rest.get(name.len()).is_some_and(|&b| is_name_end(b)) {
+            return Some(at);
+        }
+        from = at + 2;
+    }
+    None
+}
+
+/// Decodes base64 text, either alphabet, ignoring whitespace and any incomplete
+/// trailing group
+fn decode_base64(text: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for &b in text {
+        let value = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            b if b.is_ascii_whitespace() => continue,
+            _ => return None,
+        };
+        acc = acc << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// Decodes the base64 content of a manifest element, which may sit in a CDATA section
+fn manifest_data(content: &[u8]) -> Option<Vec<u8>> {
+    let start = content.iter().position(|b| !b.is_ascii_whitespace());
+    let text = &content[start.unwrap_or(content.len())..];
+    let text = match text.strip_prefix(CDATA_START) {
+        Some(text) => match find_bytes(text, CDATA_END) {
+            Some(end) => &text[..end],
+            None => text,
+        },
+        None => text,
+    };
+    decode_base64(text)
+}
+
+/// Returns the manifest store held by the `<c2pa:manifest>` element at `location`
+pub fn store_data(buf: &[u8], location: Location) -> Option<Vec<u8>> {
+    let element = buf.get(location.offset..)?;
+    let element = match location.length {
+        Some(len) => element.get(..len)?,
+        None => element,
+    };
+    if !element.get(1..)?.starts_with(MANIFEST) {
+        return None;
+    }
+    manifest_data(element_content(element, MANIFEST)?)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Character data, or the start of markup
+    #[default]
+    Content,
+    /// Inside markup ending with the given delimiter
+    Skip(&'static [u8]),
+    /// Inside a start tag that is not inspected, possibly within a quoted
+    /// attribute value
+    Tag(Option<u8>),
+}
+
+/// Resumable walk over the markup
+#[derive(Debug, Default)]
+pub struct XmlScanner {
+    /// The store location is that of the whole `<c2pa:manifest>` element, the
+    /// provenance reference comes from an `<x:xmpmeta>` or `<rdf:RDF>` element
+    pub findings: Findings,
+    /// Absolute offset of the next byte to tokenize
+    pos: usize,
+    state: State,
+    /// How far the end tag of the element at `pos` has been looked for, so that
+    /// the search is not repeated as more data arrives
+    searched: usize,
+    finished: bool,
+}
+
+impl XmlScanner {
+    /// Tokenizes `buf`, located at absolute offset `base`, and returns how many
+    /// bytes can be dropped
+    ///
+    /// Elements that need to be inspected are left for the next call until
+    /// their end tag has arrived, unless `eof` is set.
+    pub fn scan(&mut self, buf: &[u8], base: usize, eof: bool) -> usize {
+        while !self.finished {
+            // A store element is only settled once it has been seen to its end
+            if self.findings.is_settled() {
+                self.finished = true;
+                break;
+            }
+            let rel = self.pos - base;
+            let available = buf.get(rel..).unwrap_or_default();
+
+            match self.state {
+                State::Content => match available.iter().position(|&b| b == b'<') {
+                    Some(0) => {
+                        if available.len() < LOOKAHEAD && !eof {
+                            return rel;
+                        }
+                        self.markup(available, eof);
+                        if self.searched > 0 {
+                            return rel;
+                        }
+                    }
+                    Some(start) => self.pos += start,
+                    None => {
+                        self.pos += available.len();
+                        self.finished = eof;
+                        break;
+                    }
+                },
+                State::Skip(delimiter) => match find_bytes(available, delimiter) {
+                    Some(end) => {
+                        self.pos += end + delimiter.len();
+                        self.state = State::Content;
+                    }
+                    None => {
+                        // Keep what may be the start of the delimiter
+                        self.pos += available.len().saturating_sub(delimiter.len() - 1);
+                        self.finished = eof;
+                        break;
+                    }
+                },
+                State::Tag(quote) => {
+                    if !self.skip_tag(available, quote, eof) {
+                        break;
+                    }
+                }
+            }
+        }
+        self.pos - base
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Moves past the start of the markup at the start of `available`
+    fn markup(&mut self, available: &[u8], eof: bool) {
+        let (skip, state) = if available.starts_with(b"<!--") {
+            (4, State::Skip(b"-->"))
+        } else if available.starts_with(CDATA_START) {
+            (CDATA_START.len(), State::Skip(CDATA_END))
+        } else if available.starts_with(b"<?") {
+            (2, State::Skip(b"?>"))
+        } else if available.starts_with(b"<!") || available.starts_with(b"</") {
+            (2, State::Skip(b">"))
+        } else {
+            let name_len = available[1..].iter().position(|&b| is_name_end(b));
+            let name = &available[1..1 + name_len.unwrap_or(available.len() - 1)];
+            if name == MANIFEST {
+                if self.capture(available, MANIFEST, MAX_MANIFEST_LEN, eof) {
+                    return;
+                }
+            } else if let Some(&xmp) = XMP_ELEMENTS.iter().find(|&&xmp| xmp == name) {
+                if self.capture(available, xmp, MAX_XMP_LEN, eof) {
+                    return;
+                }
+            }
+            (1, State::Tag(None))
+        };
+        self.pos += skip;
+        self.state = state;
+    }
+
+    /// Inspects the element named `name` that starts `available` once it is
+    /// complete, returning `false` when it should be tokenized like any other
+    ///
+    /// `searched` is left non-zero while waiting for the end tag.
+    fn capture(
+        &mut self,
+        available: &[u8],
+        name: &'static [u8],
+        max_len: usize,
+        eof: bool,
+    ) -> bool {
+        // Cleared on every exit but the one waiting for more data
+        let searched = std::mem::take(&mut self.searched);
+        match tag_end(available) {
+            // Empty element
+            Some(end) if available[end - 1] == b'/' => return false,
+            Some(_) => {}
+            None if available.len() > max_len => return false,
+            None => {}
+        }
+        let from = searched.saturating_sub(name.len() + 2).min(available.len());
+        let element_end = find_end_tag(&available[from..], name)
+            .and_then(|pos| tag_end(&available[from + pos..]).map(|end| from + pos + end + 1));
+        let element = match element_end {
+            Some(end) if end <= max_len => &available[..end],
+            Some(_) => return false,
+            None if available.len() > max_len => return false,
+            None if eof => available,
+            None => {
+                self.searched = available.len().max(1);
+                return true;
+            }
+        };
+
+        if name == MANIFEST {
+            self.inspect_manifest(element);
+        } else if let Some(start) = tag_end(element) {
+            let content = element_content(element, name).unwrap_or_default();
+            self.findings.add_xmp(content, self.pos + start + 1);
+        }
+        self.pos += element.len();
+        true
+    }
+
+    fn inspect_manifest(&mut self, element: &[u8]) {
+        let store = match element_content(element, MANIFEST).and_then(manifest_data) {
+            Some(store) if jumbf::is_c2pa_store(&store) => store,
+            _ => return,
+        };
+        self.findings.add_store(Location {
+            offset: self.pos,
+            length: Some(element.len()),
+        });
+        if let Some(damage) = jumbf::truncation(&store) {
+            self.findings.add_damage(self.pos, damage);
+        }
+    }
+
+    /// Moves through a start tag, returning whether it ended within `available`
+    fn skip_tag(&mut self, available: &[u8], mut quote: Option<u8>, eof: bool) -> bool {
+        for (i, &b) in available.iter().enumerate() {
+            match quote {
+                Some(q) if b == q => quote = None,
+                Some(_) => {}
+                None if b == b'"' || b == b'\'' => quote = Some(b),
+                None if b == b'>' => {
+                    self.pos += i + 1;
+                    self.state = State::Content;
+                    return true;
+                }
+                None => {}
+            }
+        }
+        self.pos += available.len();
+        self.state = State::Tag(quote);
+        self.finished = eof;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::{detect, DetectionResult};
+    use crate::extract::extract_store;
+    use crate::stream::StreamDetector;
+
+    fn encode_base64(data: &[u8]) -> Vec<u8> {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = Vec::new();
+        for (i, group) in data.chunks(3).enumerate() {
+            if i > 0 && i % 19 == 0 {
+                out.push(b'\n');
+            }
+            let n = group.iter().fold(0u32, |n, &b| n << 8 | b as u32) << (8 * (3 - group.len()));
+            for k in 0..4 {
+                if k <= group.len() {
+                    out.push(ALPHABET[(n >> (18 - 6 * k)) as usize & 0x3F]);
+                } else {
+                    out.push(b'=');
+                }
+            }
+        }
+        out
+    }
+
+    fn svg(store: &[u8]) -> Vec<u8> {
+        let mut asset = br#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- <c2pa:manifest>not this one</c2pa:manifest> -->
+<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+  <path d="M0 0L10 10" data-note="a > b"/>
+  <metadata>
+    <c2pa:manifest xmlns:c2pa="http://c2pa.org/manifest">
+"#
+        .to_vec();
+        asset.extend_from_slice(&encode_base64(store));
+        asset.extend_from_slice(b"\n    </c2pa:manifest>\n  </metadata>\n</svg>\n");
+        asset
+    }
+
+    #[test]
+    fn finds_base64_store_in_metadata() {
+        let jpeg = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        let store = extract_store(jpeg).unwrap().bytes;
+        let asset = svg(&store);
+
+        let mut scanner = XmlScanner::default();
+        scanner.scan(&asset, 0, true);
+
+        let location = scanner.findings.store().unwrap();
+        assert_eq!(
+            location.offset,
+            find_bytes(&asset, b"<c2pa:manifest xmlns").unwrap()
+        );
+        assert_eq!(store_data(&asset, location), Some(store));
+
+        let whole = detect(&asset);
+        let mut detector = StreamDetector::new();
+        for chunk in asset.chunks(4093) {
+            detector.push(chunk);
+        }
+        assert_eq!(detector.finish(), whole);
+    }
+
+    #[test]
+    fn streams_past_oversized_xmp() {
+        let jpeg = include_bytes!("../../../tools/testing/fixtures/images/CAICAI.jpg");
+        let store = extract_store(jpeg).unwrap().bytes;
+        let mut asset = br#"<svg xmlns="http://www.w3.org/2000/svg"><metadata><x:xmpmeta xmlns:x="adobe:ns:meta/">"#.to_vec();
+        asset.resize(asset.len() + MAX_XMP_LEN, b' ');
+        asset.extend_from_slice(b"</x:xmpmeta></metadata>");
+        let tail = svg(&store);
+        let svg_start = find_bytes(&tail, b"<svg").unwrap();
+        asset.extend_from_slice(&tail[svg_start..]);
+
+        let whole = detect(&asset);
+        assert!(matches!(whole, DetectionResult::ManifestStore { .. }));
+        for &chunk_len in &[1, 64] {
+            let mut detector = StreamDetector::new();
+            for chunk in asset.chunks(chunk_len) {
+                if detector.push(chunk) {
+                    break;
+                }
+            }
+            assert_eq!(detector.finish(), whole, "chunks of {}", chunk_len);
+        }
+    }
+
+    #[test]
+    fn reads_xmp_in_metadata() {
+        let asset = br#"<svg xmlns="http://www.w3.org/2000/svg"><metadata><x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF><rdf:Description dcterms:provenance="https://example.com/a.c2pa"/></rdf:RDF></x:xmpmeta></metadata></svg>"#;
+
+        let mut scanner = XmlScanner::default();
+        scanner.scan(asset, 0, true);
+
+        let provenance = scanner.findings.provenance().unwrap();
+        assert_eq!(
+            provenance.url.as_deref(),
+            Some("https://example.com/a.c2pa")
+        );
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Extraction of remote manifest URLs from XMP packets.
+//!
+//! The URL may be written as an attribute (`dcterms:provenance="…"`), as element
+//! content (`<dcterms:provenance>…</dcterms:provenance>`) or as an `rdf:resource`
+//! on an empty element. Older writers use `dc:provenance` instead.
+
+use twoway::find_bytes;
+
+pub const DCTERMS_PROVENANCE: &[u8] = b"dcterms:provenance";
+pub const DC_PROVENANCE: &[u8] = b"dc:provenance";
+
+const RDF_RESOURCE: &[u8] = b"rdf:resource";
+
+/// How far past the property name we look for the end of its value
+///
+/// Bounding this keeps the amount of data a streaming scan has to hold on to small.
+pub const MAX_VALUE_SCAN: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// Offset of the property name
+    pub offset: usize,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Search {
+    Found(Provenance),
+    /// A reference starts at this offset but its value runs past the end of the buffer
+    Incomplete(usize),
+    NotFound,
+}
+
+enum Value {
+    Url(String),
+    /// A reference without a usable URL
+    Empty,
+    /// The name is not used as an attribute or element here (e.g. a closing tag)
+    NotReference,
+    Truncated,
+}
+
+/// Finds the first provenance reference in `buf`
+///
+/// Unless `eof` is set, a reference whose value is cut off by the end of `buf`
+/// is reported as `Search::Incomplete` so the caller can retry with more data.
+pub fn find_provenance(buf: &[u8], eof: bool) -> Search {
+    for (pos, name) in Names::new(buf) {
+        let url = match read_value(buf, pos, name.len()) {
+            Value::Url(url) => Some(url),
+            Value::Empty => None,
+            Value::NotReference => continue,
+            Value::Truncated if eof => None,
+            Value::Truncated => return Search::Incomplete(pos),
+        };
+        return Search::Found(Provenance { offset: pos, url });
+    }
+    Search::NotFound
+}
+
+/// Finds every provenance reference in a complete packet
+pub fn find_all_provenance(buf: &[u8]) -> Vec<Provenance> {
+    Names::new(buf)
+        .filter_map(|(pos, name)| {
+            let url = match read_value(buf, pos, name.len()) {
+                Value::Url(url) => Some(url),
+                Value::Empty | Value::Truncated => None,
+                Value::NotReference => return None,
+            };
+            Some(Provenance { offset: pos, url })
+        })
+        .collect()
+}
+
+const NAMES: [&[u8]; 2] = [DCTERMS_PROVENANCE, DC_PROVENANCE];
+
+/// Positions of the property names in a buffer, in order
+///
+/// The next match of each name is kept, and only the name just returned is
+/// searched for again, so that the buffer is searched once per name.
+struct Names<'a> {
+    buf: &'a [u8],
+    next: [Option<usize>; 2],
+}
+
+impl<'a> Names<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Names {
+            buf,
+            next: [find_bytes(buf, NAMES[0]), find_bytes(buf, NAMES[1])],
+        }
+    }
+}
+
+impl Iterator for Names<'_> {
+    type Item = (usize, &'static [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, pos) = self
+            .next
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pos)| Some((i, (*pos)?)))
+            .min_by_key(|&(_, pos)| pos)?;
+        let name = NAMES[i];
+        let from = pos + name.len();
+        self.next[i] = find_bytes(&self.buf[from..], name).map(|next| from + next);
+        Some((pos, name))
+    }
+}
+
+fn read_value(buf: &[u8], pos: usize, name_len: usize) -> Value {
+    // The name must start an element or an attribute, whatever follows it
+    let prev = match pos.checked_sub(1).map(|i| buf[i]) {
+        Some(prev) if prev == b'<' || prev.is_ascii_whitespace() => prev,
+        _ => return Value::NotReference,
+    };
+    let rest = &buf[pos + name_len..];
+    match rest.first() {
+        Some(b) if b.is_ascii_whitespace() || b"=>/".contains(b) => {}
+        Some(_) => return Value::NotReference,
+        None => return Value::Truncated,
+    }
+
+    if prev == b'<' {
+        read_element(rest)
+    } else {
+        read_attribute(rest)
+    }
+}
+
+fn read_element(rest: &[u8]) -> Value {
+    let tag_end = match find_within(rest, b">") {
+        Ok(end) => end,
+        Err(value) => return value,
+    };
+    let tag = &rest[..tag_end];
+
+    if let Some(attr) = find_bytes(tag, RDF_RESOURCE) {
+        if let Value::Url(url) = read_attribute(&tag[attr + RDF_RESOURCE.len()..]) {
+            return Value::Url(url);
+        }
+    }
+    if tag.ends_with(b"/") {
+        return Value::Empty;
+    }
+
+    let content = &rest[tag_end + 1..];
+    match find_within(content, b"<") {
+        Ok(end) => url_from(&content[..end]),
+        Err(value) => value,
+    }
+}
+
+fn read_attribute(rest: &[u8]) -> Value {
+    let mut i = skip_whitespace(rest, 0);
+    match rest.get(i) {
+        Some(b'=') => i += 1,
+        Some(_) => return Value::NotReference,
+        None => return Value::Truncated,
+    }
+    i = skip_whitespace(rest, i);
+    let quote = match rest.get(i) {
+        Some(&q) if q == b'"' || q == b'\'' => q,
+        Some(_) => return Value::NotReference,
+        None => return Value::Truncated,
+    };
+
+    let value = &rest[i + 1..];
+    match find_within(value, &[quote]) {
+        Ok(end) => url_from(&value[..end]),
+        Err(value) => value,
+    }
+}
+
+/// Finds `needle` within the first `MAX_VALUE_SCAN` bytes of `buf`
+fn find_within(buf: &[u8], needle: &[u8]) -> Result<usize, Value> {
+    let window = &buf[..buf.len().min(MAX_VALUE_SCAN)];
+    match find_bytes(window, needle) {
+        Some(pos) => Ok(pos),
+        None if buf.len() > MAX_VALUE_SCAN => Err(Value::Empty),
+        None => Err(Value::Truncated),
+    }
+}
+
+fn skip_whitespace(buf: &[u8], mut i: usize) -> usize {
+    while buf.get(i).is_some_and(u8::is_ascii_whitespace) {
+        i += 1;
+    }
+    i
+}
+
+fn url_from(raw: &[u8]) -> Value {
+    let url = unescape(String::from_utf8_lossy(raw).trim());
+    if url.is_empty() {
+        Value::Empty
+    } else {
+        Value::Url(url)
+    }
+}
+
+/// Resolves the predefined XML entities and character references
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest.find(';').and_then(|semi| {
+            let entity = &rest[1..semi];
+            let ch = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    u32::from_str_radix(&entity[2..], 16)
+                        .ok()
+                        .and_then(std::char::from_u32)
+                }
+                _ if entity.starts_with('#') => {
+                    entity[1..].parse().ok().and_then(std::char::from_u32)
+                }
+                _ => None,
+            };
+            ch.map(|ch| (ch, semi + 1))
+        });
+
+        match decoded {
+            Some((ch, len)) => {
+                out.push(ch);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(xmp: &str) -> Option<String> {
+        match find_provenance(xmp.as_bytes(), true) {
+            Search::Found(provenance) => provenance.url,
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn reads_attribute_form() {
+        let xmp = r#"<rdf:Description xmlns:dcterms="http://purl.org/dc/terms/" dcterms:provenance="https://example.com/a.c2pa?x=1&amp;y=2"/>"#;
+
+        assert_eq!(
+            url(xmp).as_deref(),
+            Some("https://example.com/a.c2pa?x=1&y=2")
+        );
+    }
+
+    #[test]
+    fn reads_element_forms() {
+        let content = "<dcterms:provenance>\n  https://example.com/b.c2pa\n</dcterms:provenance>";
+        let resource = r#"<dc:provenance rdf:resource="https://example.com/c.c2pa"/>"#;
+
+        assert_eq!(url(content).as_deref(), Some("https://example.com/b.c2pa"));
+        assert_eq!(url(resource).as_deref(), Some("https://example.com/c.c2pa"));
+    }
+
+    #[test]
+    fn skips_many_names_used_as_values() {
+        let mut xmp = b"hello".to_vec();
+        for _ in 0..40_000 {
+            xmp.extend_from_slice(b"xdc:provenance");
+        }
+        xmp.extend_from_slice(br#" dc:provenance="https://example.com/d.c2pa""#);
+
+        assert_eq!(
+            find_provenance(&xmp, true),
+            Search::Found(Provenance {
+                offset: 5 + 40_000 * 14 + 1,
+                url: Some("https://example.com/d.c2pa".to_string()),
+            })
+        );
+        assert_eq!(find_all_provenance(&xmp).len(), 1);
+    }
+
+    #[test]
+    fn ignores_name_ending_the_buffer_within_another_name() {
+        let xmp = b"<x:xmpmeta>xdc:provenance";
+
+        assert_eq!(find_provenance(xmp, true), Search::NotFound);
+        assert_eq!(find_provenance(xmp, false), Search::NotFound);
+    }
+
+    #[test]
+    fn reports_truncated_value() {
+        let xmp = br#" dcterms:provenance="https://exam"#;
+
+        assert_eq!(find_provenance(xmp, false), Search::Incomplete(1));
+    }
+}
@@ -0,0 +1,261 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! ZIP central directory reader that locates the archive entry carrying a C2PA
+//! Manifest Store in Office Open XML, EPUB and OpenDocument files.
+//!
+//! Entry data is usually compressed, so the store is found by its name in the
+//! central directory at the end of the archive rather than by its bytes.
+
+use crate::detection::{Damage, Findings, Location};
+
+/// Name of the entry holding the store
+pub const MANIFEST_ENTRY: &[u8] = b"META-INF/content_credential.c2pa";
+
+const LOCAL_HEADER: &[u8] = b"PK\x03\x04";
+const CENTRAL_HEADER: &[u8] = b"PK\x01\x02";
+const END_OF_CENTRAL_DIRECTORY: &[u8] = b"PK\x05\x06";
+
+const LOCAL_HEADER_LEN: usize = 30;
+const CENTRAL_HEADER_LEN: usize = 46;
+const END_OF_CENTRAL_DIRECTORY_LEN: usize = 22;
+const MAX_COMMENT_LEN: usize = 0xFFFF;
+
+/// Compression method of entries kept as-is
+const STORED: u16 = 0;
+/// General purpose flag set when sizes follow the data rather than the local header
+const DATA_DESCRIPTOR: u16 = 0x08;
+
+/// Entry holding the MIME type of EPUB and OpenDocument files, stored first
+const MIMETYPE_ENTRY: &[u8] = b"mimetype";
+
+pub fn is_zip(buf: &[u8]) -> bool {
+    buf.starts_with(LOCAL_HEADER)
+}
+
+fn u16_at(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes([*buf.get(pos)?, *buf.get(pos + 1)?]))
+}
+
+fn u32_at(buf: &[u8], pos: usize) -> Option<u32> {
+    let bytes = buf.get(pos..pos + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// A central directory entry
+struct Entry<'a> {
+    name: &'a [u8],
+    compressed_len: usize,
+    local_offset: usize,
+}
+
+/// Returns the range of the central directory, going by the last end of central
+/// directory record of the archive
+///
+/// ZIP64 archives, whose record fields overflow, have no readable directory.
+fn central_directory(buf: &[u8]) -> Option<(usize, usize)> {
+    let search_from = buf
+        .len()
+        .saturating_sub(END_OF_CENTRAL_DIRECTORY_LEN + MAX_COMMENT_LEN);
+    let end = search_from
+        + buf[search_from..]
+            .windows(END_OF_CENTRAL_DIRECTORY.len())
+            .rposition(|window| window == END_OF_CENTRAL_DIRECTORY)?;
+    let start = u32_at(buf, end + 16)? as usize;
+    Some((start, end)).filter(|_| start <= end)
+}
+
+/// Iterates over the entries of the central directory spanning `start..end`
+fn entries(buf: &[u8], start: usize, end: usize) -> impl Iterator<Item = Entry<'_>> {
+    let mut pos = start;
+    std::iter::from_fn(move || {
+        let header = buf.get(pos..end)?;
+        if !header.starts_with(CENTRAL_HEADER) {
+            return None;
+        }
+        let compressed_len = u32_at(header, 20)? as usize;
+        let name_len = u16_at(header, 28)? as usize;
+        let extra_len = u16_at(header, 30)? as usize;
+        let comment_len = u16_at(header, 32)? as usize;
+        let local_offset = u32_at(header, 42)? as usize;
+        let name = header.get(CENTRAL_HEADER_LEN..CENTRAL_HEADER_LEN + name_len)?;
+        pos += CENTRAL_HEADER_LEN + name_len + extra_len + comment_len;
+        Some(Entry {
+            name,
+            compressed_len,
+            local_offset,
+        })
+    })
+}
+
+/// Location of the local header and data of `entry`
+///
+/// The extra field of the local header may differ from the one in the central
+/// directory, so its length is read from the local header when present.
+fn entry_location(buf: &[u8], entry: &Entry) -> Location {
+    let local = buf
+        .get(entry.local_offset..)
+        .filter(|local| local.starts_with(LOCAL_HEADER));
+    let header_len = match local.and_then(|local| Some((u16_at(local, 26)?, u16_at(local, 28)?))) {
+        Some((name_len, extra_len)) => LOCAL_HEADER_LEN + name_len as usize + extra_len as usize,
+        None => LOCAL_HEADER_LEN + entry.name.len(),
+    };
+    Location {
+        offset: entry.local_offset,
+        length: Some(header_len + entry.compressed_len),
+    }
+}
+
+/// Returns the manifest store held by the entry at `location`, unless it is compressed
+pub fn store_data(buf: &[u8], location: Location) -> Option<&[u8]> {
+    let local = buf.get(location.offset..location.offset + location.length?)?;
+    if !local.starts_with(LOCAL_HEADER) || u16_at(local, 8)? != STORED {
+        return None;
+    }
+    let data_start = LOCAL_HEADER_LEN + u16_at(local, 26)? as usize + u16_at(local, 28)? as usize;
+    local.get(data_start..)
+}
+
+/// Iterates over the names and data of the entries at the start of the archive by
+/// walking their local headers, up to the first entry whose size only follows its data
+pub fn leading_entries(buf: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        let header = buf
+            .get(pos..)
+            .filter(|header| header.starts_with(LOCAL_HEADER))?;
+        if u16_at(header, 6)? & DATA_DESCRIPTOR != 0 {
+            return None;
+        }
+        let compressed_len = u32_at(header, 18)? as usize;
+        let name_len = u16_at(header, 26)? as usize;
+        let extra_len = u16_at(header, 28)? as usize;
+        let name = header.get(LOCAL_HEADER_LEN..LOCAL_HEADER_LEN + name_len)?;
+        let data_start = LOCAL_HEADER_LEN + name_len + extra_len;
+        let data_end = data_start.saturating_add(compressed_len);
+        let data = &header[data_start.min(header.len())..data_end.min(header.len())];
+        pos += data_end;
+        Some((name, data))
+    })
+}
+
+/// Returns the content of the `mimetype` entry EPUB and OpenDocument files start with
+pub fn declared_mime_type(buf: &[u8]) -> Option<&[u8]> {
+    let header = buf.get(..LOCAL_HEADER_LEN)?;
+    if u16_at(header, 8)? != STORED {
+        return None;
+    }
+    leading_entries(buf)
+        .next()
+        .filter(|(name, _)| *name == MIMETYPE_ENTRY)
+        .map(|(_, data)| data)
+}
+
+/// Reads the central directory once the end of the archive is available
+#[derive(Debug, Default)]
+pub struct ZipScanner {
+    /// The store location covers the local header and data of the manifest entry
+    pub findings: Findings,
+    finished: bool,
+}
+
+impl ZipScanner {
+    /// The central directory lives at the end of the archive, so nothing is
+    /// scanned (or dropped) until `eof` is set
+    pub fn scan(&mut self, buf: &[u8], _base: usize, eof: bool) -> usize {
+        if !eof {
+            return 0;
+        }
+        if let Some((start, end)) = central_directory(buf) {
+            self.find_stores(buf, start, end);
+        }
+        self.finished = true;
+        buf.len()
+    }
+
+    /// Local file entries precede the central directory, and one running into it
+    /// is reported as truncated
+    fn find_stores(&mut self, buf: &[u8], start: usize, end: usize) {
+        let manifests = entries(buf, start, end).filter(|entry| entry.name == MANIFEST_ENTRY);
+        for entry in manifests.filter(|entry| entry.local_offset < start) {
+            let location = entry_location(buf, &entry);
+            let expected = location.length.unwrap_or_default();
+            if location.offset.saturating_add(expected) > start {
+                let damage = Damage::Truncated {
+                    container: "local file".to_string(),
+                    expected,
+                    available: start - location.offset,
+                };
+                self.findings.add_damage(location.offset, damage);
+            } else {
+                self.findings.add_store(location);
+            }
+            if self.findings.is_settled() {
+                break;
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_manifest_entry() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.docx");
+
+        let mut scanner = ZipScanner::default();
+        scanner.scan(asset, 0, true);
+
+        let store = scanner.findings.store().unwrap();
+        assert_eq!(store.offset, 713);
+        assert_eq!(store.length, Some(30 + MANIFEST_ENTRY.len() + 886_180));
+        assert!(crate::jumbf::is_c2pa_store(
+            store_data(asset, store).unwrap()
+        ));
+    }
+
+    #[test]
+    fn reads_only_stored_entries() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.epub");
+        let mut scanner = ZipScanner::default();
+        scanner.scan(asset, 0, true);
+        let store = scanner.findings.store().unwrap();
+        // Mark the entry as deflated
+        let mut deflated = asset.to_vec();
+        deflated[store.offset + 8] = 8;
+
+        assert!(store_data(asset, store).is_some());
+        assert_eq!(store_data(&deflated, store), None);
+        assert_eq!(
+            declared_mime_type(asset),
+            Some(&b"application/epub+zip"[..])
+        );
+    }
+
+    #[test]
+    fn reports_entry_running_into_central_directory() {
+        let asset = include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.docx");
+        let mut asset = asset.to_vec();
+        // Grow the compressed size in the central directory entry
+        let entry = asset.len() - 22 - CENTRAL_HEADER_LEN - MANIFEST_ENTRY.len();
+        asset[entry + 20..entry + 24].copy_from_slice(&1_000_000u32.to_le_bytes());
+
+        let mut scanner = ZipScanner::default();
+        scanner.scan(&asset, 0, true);
+
+        assert!(matches!(
+            scanner.findings.damage(),
+            Some((713, Damage::Truncated { expected, .. })) if expected == 30 + MANIFEST_ENTRY.len() + 1_000_000
+        ));
+    }
+}
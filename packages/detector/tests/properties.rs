@@ -0,0 +1,189 @@
+// Copyright 2021 Adobe
+// All Rights Reserved.
+//
+// NOTICE: Adobe permits you to use, modify, and distribute this file in
+// accordance with the terms of the Adobe license agreement accompanying
+// it.
+
+//! Properties of the scanners over mutated fixtures and random data behind the
+//! header of each supported format: scans never panic, which in safe Rust also
+//! covers reading out of bounds, report locations within the input, and give the
+//! same answer whether the input is fed whole, in chunks or through a reader.
+
+use detector::{
+    detect, detect_head, detect_reader, extract_store, parse_tree, plan_ranges, scan_all,
+    scan_all_reader, sniff_format, summarize, DetectionResult, Occurrence, StreamDetector,
+};
+use proptest::prelude::*;
+use proptest::sample::Index;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Leading bytes that route input to each format walker
+static HEADERS: [&[u8]; 13] = [
+    b"\xFF\xD8\xFF",
+    b"\x89PNG\r\n\x1A\n",
+    b"\0\0\0\x08ftyp",
+    b"RIFF\0\0\0\0WEBP",
+    b"%PDF-1.7\n",
+    b"II*\0\x08\0\0\0",
+    b"GIF89a",
+    b"\0\0\0\x0CJXL \r\n\x87\n",
+    b"ID3\x04\0\0",
+    b"fLaC",
+    b"PK\x03\x04",
+    b"<svg>",
+    b"",
+];
+
+/// Fixtures are cut to this length to keep each case fast
+const MAX_FIXTURE_LEN: usize = 256 * 1024;
+
+fn fixtures() -> Vec<Vec<u8>> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tools/testing/fixtures/images");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            let mut data = fs::read(path).unwrap();
+            data.truncate(MAX_FIXTURE_LEN);
+            data
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+enum Edit {
+    Set(Index, u8),
+    Insert(Index, Vec<u8>),
+    Remove(Index, usize),
+    Truncate(Index),
+}
+
+fn edit() -> impl Strategy<Value = Edit> {
+    prop_oneof![
+        4 => (any::<Index>(), any::<u8>()).prop_map(|(at, byte)| Edit::Set(at, byte)),
+        1 => (any::<Index>(), prop::collection::vec(any::<u8>(), 1..64))
+            .prop_map(|(at, bytes)| Edit::Insert(at, bytes)),
+        1 => (any::<Index>(), 1..64usize).prop_map(|(at, len)| Edit::Remove(at, len)),
+        1 => any::<Index>().prop_map(Edit::Truncate),
+    ]
+}
+
+fn apply(data: &mut Vec<u8>, edit: &Edit) {
+    if data.is_empty() {
+        return;
+    }
+    match edit {
+        Edit::Set(at, byte) => {
+            let at = at.index(data.len());
+            data[at] = *byte;
+        }
+        Edit::Insert(at, bytes) => {
+            let at = at.index(data.len());
+            data.splice(at..at, bytes.iter().copied());
+        }
+        Edit::Remove(at, len) => {
+            let at = at.index(data.len());
+            let end = data.len().min(at + len);
+            data.drain(at..end);
+        }
+        Edit::Truncate(at) => data.truncate(at.index(data.len())),
+    }
+}
+
+/// A fixture with a few bytes changed, inserted or removed
+fn mutated_fixture() -> impl Strategy<Value = Vec<u8>> {
+    let fixtures = fixtures();
+    (
+        prop::sample::select(fixtures),
+        prop::collection::vec(edit(), 1..8),
+    )
+        .prop_map(|(mut data, edits)| {
+            for edit in &edits {
+                apply(&mut data, edit);
+            }
+            data
+        })
+}
+
+/// Random bytes behind the header of one of the formats
+fn random_asset() -> impl Strategy<Value = Vec<u8>> {
+    (
+        prop::sample::select(&HEADERS[..]),
+        prop::collection::vec(any::<u8>(), 0..2048),
+    )
+        .prop_map(|(header, data)| [header, &data[..]].concat())
+}
+
+fn feed(detector: &mut StreamDetector, data: &[u8], chunk_len: usize) {
+    let mut offset = 0;
+    while offset < data.len() {
+        offset += detector.take_skip(data.len() - offset);
+        let end = data.len().min(offset + chunk_len);
+        if offset >= end || detector.push(&data[offset..end]) {
+            break;
+        }
+        offset = end;
+    }
+}
+
+fn check(data: &[u8], chunk_len: usize) -> Result<(), TestCaseError> {
+    let result = detect(data);
+    let report = scan_all(data);
+
+    let mut detector = StreamDetector::new();
+    feed(&mut detector, data, chunk_len);
+    prop_assert_eq!(detector.finish(), result.clone());
+    let mut detector = StreamDetector::exhaustive();
+    feed(&mut detector, data, chunk_len);
+    prop_assert_eq!(detector.finish_report(), report.clone());
+
+    prop_assert_eq!(
+        detect_reader(&mut Cursor::new(data)).unwrap(),
+        result.clone()
+    );
+    prop_assert_eq!(
+        scan_all_reader(&mut Cursor::new(data)).unwrap(),
+        report.clone()
+    );
+
+    if let DetectionResult::ManifestStore { offset, length, .. } = result {
+        prop_assert!(offset + length.unwrap_or(1) <= data.len());
+    }
+    for occurrence in &report.occurrences {
+        prop_assert!(occurrence.offset() < data.len());
+        if let Occurrence::ManifestStore {
+            offset,
+            length: Some(length),
+        } = occurrence
+        {
+            prop_assert!(offset + length <= data.len());
+        }
+    }
+
+    extract_store(data);
+    parse_tree(data);
+    summarize(data);
+    sniff_format(data);
+    detect_head(&data[..data.len() / 2]);
+    plan_ranges(&data[..data.len() / 2], data.len());
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn mutated_fixtures_scan_the_same_in_chunks(data in mutated_fixture(), chunk_len in 1..8192usize) {
+        check(&data, chunk_len)?;
+    }
+
+    #[test]
+    fn random_assets_scan_the_same_in_chunks(data in random_asset(), chunk_len in 1..512usize) {
+        check(&data, chunk_len)?;
+    }
+}
@@ -85,4 +85,62 @@ pub mod tests {
         let result = get_manifest_store_data(test_asset, "image/jpeg", None).await;
         assert!(result.is_ok());
     }
+
+    /// Reads one of the `E-dat-CAICAI` fixtures, whose store is copied from
+    /// CAICAI.jpg and so fails its data hash
+    async fn assert_data_hash_mismatch(test_asset: &[u8], mime_type: &str) {
+        let reader = get_manifest_store_data(test_asset, mime_type, None)
+            .await
+            .unwrap();
+        let codes: Vec<&str> = reader
+            .validation_status()
+            .unwrap_or_default()
+            .iter()
+            .map(|status| status.code())
+            .collect();
+        assert!(
+            codes.contains(&"assertion.dataHash.mismatch"),
+            "{:?}",
+            codes
+        );
+    }
+
+    #[wasm_bindgen_test]
+    pub async fn test_manifest_store_data_gif() {
+        let test_asset = include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.gif");
+
+        assert_data_hash_mismatch(test_asset, "image/gif").await;
+    }
+
+    #[wasm_bindgen_test]
+    pub async fn test_manifest_store_data_svg() {
+        let test_asset = include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.svg");
+
+        assert_data_hash_mismatch(test_asset, "image/svg+xml").await;
+    }
+
+    #[wasm_bindgen_test]
+    pub async fn test_manifest_store_data_docx() {
+        let test_asset = include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.docx");
+
+        assert_data_hash_mismatch(
+            test_asset,
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        )
+        .await;
+    }
+
+    #[wasm_bindgen_test]
+    pub async fn test_manifest_store_data_epub() {
+        let test_asset = include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.epub");
+
+        assert_data_hash_mismatch(test_asset, "application/epub+zip").await;
+    }
+
+    #[wasm_bindgen_test]
+    pub async fn test_manifest_store_data_mp3() {
+        let test_asset = include_bytes!("../../../tools/testing/fixtures/images/E-dat-CAICAI.mp3");
+
+        assert_data_hash_mismatch(test_asset, "audio/mpeg").await;
+    }
 }